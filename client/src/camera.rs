@@ -0,0 +1,113 @@
+use common::map::{EdgeMode, Map};
+
+/// Smallest cell size, in logical pixels, the arena is shrunk to so it fits on screen.
+/// The camera follows the snake in arenas that don't fit with it.
+const MIN_FIT_CELL_SIZE: f32 = 10.0;
+const MIN_ZOOM: f32 = 0.25;
+const MAX_ZOOM: f32 = 4.0;
+const ZOOM_STEP: f32 = 1.25;
+/// How fast the camera catches up with its target, higher is snappier
+const FOLLOW_SPEED: f32 = 6.0;
+
+/// Decides which part of the arena is on screen
+pub struct Camera {
+    /// Arena position at the centre of the screen, in cells
+    pub center: (f32, f32),
+    /// Size of a cell, in pixels
+    pub cell_size: f32,
+    /// Zoom level picked by the player, 1 being the default
+    pub zoom: f32,
+    /// Jump straight to the next target instead of sliding there,
+    /// so that (re)spawning doesn't sweep across the arena
+    snap: bool,
+}
+
+impl Camera {
+    pub fn new() -> Self {
+        Self {
+            center: (0.0, 0.0),
+            cell_size: MIN_FIT_CELL_SIZE,
+            zoom: 1.0,
+            snap: true,
+        }
+    }
+
+    pub fn zoom(&mut self, zoom_in: bool) {
+        let zoom = if zoom_in {
+            self.zoom * ZOOM_STEP
+        } else {
+            self.zoom / ZOOM_STEP
+        };
+
+        self.zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+    }
+
+    /// Size of the visible area, in cells
+    pub fn view_size(&self, screen: (u32, u32)) -> (f32, f32) {
+        (
+            screen.0 as f32 / self.cell_size,
+            screen.1 as f32 / self.cell_size,
+        )
+    }
+
+    /// Whether the whole arena fits on screen
+    pub fn sees_all(&self, map: &Map, screen: (u32, u32)) -> bool {
+        let view = self.view_size(screen);
+
+        view.0 >= map.width as f32 && view.1 >= map.height as f32
+    }
+
+    /// Scales the arena to the screen and moves the camera towards `target`,
+    /// the arena centre if there is nothing to follow
+    pub fn update(
+        &mut self,
+        target: Option<(f32, f32)>,
+        map: &Map,
+        screen: (u32, u32),
+        dpi_scale: f32,
+        dt: f32,
+    ) {
+        let (width, height) = (map.width as f32, map.height as f32);
+
+        // Fit the whole arena, keeping its aspect ratio
+        let fit = (screen.0 as f32 / width).min(screen.1 as f32 / height);
+        self.cell_size = fit.max(MIN_FIT_CELL_SIZE * dpi_scale) * self.zoom;
+
+        let view = self.view_size(screen);
+        let wrap = map.edges == EdgeMode::Wrap;
+
+        // Frame rate independent exponential smoothing
+        let factor = if self.snap {
+            1.0
+        } else {
+            1.0 - (-dt * FOLLOW_SPEED).exp()
+        };
+        self.snap = target.is_none();
+
+        let target = target.unwrap_or((width / 2.0, height / 2.0));
+
+        self.center = (
+            follow(self.center.0, target.0, width, view.0, wrap, factor),
+            follow(self.center.1, target.1, height, view.1, wrap, factor),
+        );
+    }
+}
+
+/// Moves the camera along one axis
+fn follow(center: f32, target: f32, size: f32, view: f32, wrap: bool, factor: f32) -> f32 {
+    // The whole axis is visible, keep the arena centred
+    if view >= size {
+        return size / 2.0;
+    }
+
+    if wrap {
+        // Go the short way around the edges
+        let diff = (target - center + size / 2.0).rem_euclid(size) - size / 2.0;
+
+        (center + diff * factor).rem_euclid(size)
+    } else {
+        let center = center + (target - center) * factor;
+
+        center.clamp(view / 2.0, size - view / 2.0)
+    }
+}
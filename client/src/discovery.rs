@@ -0,0 +1,100 @@
+use std::{
+    io::ErrorKind,
+    net::{IpAddr, Ipv4Addr, UdpSocket},
+};
+
+use common::{
+    discovery::{probe_packet, DiscoveredServer, ServerInfo, ServerList, DISCOVERY_PORT},
+    packet::ReadablePacket,
+};
+
+/// Looks for servers on the LAN by broadcasting UDP probes
+pub struct Discovery {
+    /// None if no UDP socket could be opened, nothing is ever found then
+    socket: Option<UdpSocket>,
+    servers: ServerList,
+}
+
+fn open_socket() -> std::io::Result<UdpSocket> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+
+    socket.set_broadcast(true)?;
+    socket.set_nonblocking(true)?;
+
+    Ok(socket)
+}
+
+/// Address of this machine on the LAN, the one broadcasts are sent from.
+/// Connecting a UDP socket sends nothing, it only picks the route.
+fn local_ip() -> Option<IpAddr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+
+    socket.set_broadcast(true).ok()?;
+    socket.connect((Ipv4Addr::BROADCAST, DISCOVERY_PORT)).ok()?;
+
+    socket.local_addr().ok().map(|addr| addr.ip())
+}
+
+impl Discovery {
+    pub fn new() -> Self {
+        let socket = open_socket()
+            .map_err(|err| eprintln!("WARN: Could not open the discovery socket: {err}"))
+            .ok();
+
+        Self {
+            socket,
+            servers: ServerList::new(local_ip()),
+        }
+    }
+
+    pub fn servers(&self) -> &[DiscoveredServer] {
+        self.servers.servers()
+    }
+
+    /// Forgets the servers found so far and asks again
+    pub fn probe(&mut self) {
+        self.servers.clear();
+
+        let Some(socket) = &self.socket else {
+            return;
+        };
+
+        // Loopback too, for servers running on this machine
+        for ip in [Ipv4Addr::BROADCAST, Ipv4Addr::LOCALHOST] {
+            if let Err(err) = socket.send_to(&probe_packet(), (ip, DISCOVERY_PORT)) {
+                eprintln!("WARN: Could not send a discovery probe to {ip}: {err}");
+            }
+        }
+    }
+
+    /// Collects the answers received so far
+    pub fn poll(&mut self) {
+        let Some(socket) = &self.socket else {
+            return;
+        };
+
+        let mut buffer = [0u8; 512];
+
+        loop {
+            let (size, addr) = match socket.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => return,
+                Err(err) => {
+                    eprintln!("WARN: Could not read a discovery answer: {err}");
+                    return;
+                }
+            };
+
+            // Skip the length prefix, a datagram holds a single packet
+            let Some(info) = buffer[..size]
+                .get(2..)
+                .and_then(ReadablePacket::try_from_bytes)
+                .and_then(|mut packet| ServerInfo::from_packet(&mut packet))
+            else {
+                continue;
+            };
+
+            self.servers.add(addr.ip(), info);
+        }
+    }
+}
@@ -1,11 +1,38 @@
-use std::collections::{HashMap, VecDeque};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    time::{Duration, Instant},
+};
 
-use common::util::Point;
+use common::{
+    map::{Bounds, Map},
+    power_up::PowerUp,
+    snapshot::{Snapshot, SnapshotHistory, SnapshotUpdate},
+    util::Point,
+};
+
+/// Chat messages kept around, the renderer only shows the last few
+const MAX_CHAT_HISTORY: usize = 20;
+
+/// A chat message, from the server itself if the snake ID is 0
+pub struct ChatLine {
+    pub snake_id: u8,
+    pub sender: String,
+    pub message: String,
+    pub received: Instant,
+}
 
 #[derive(PartialEq)]
 pub enum State {
     Joining,
     Playing,
+    /// The server refused us, with the reason
+    Rejected(String),
+    /// The connection was lost, with the reason
+    Disconnected(String),
+    /// The connection was lost, with the reason, trying to get our snake back
+    Reconnecting(String),
+    /// The server shut down, with the reason it gave
+    ServerClosed(String),
 }
 
 #[derive(Clone, Copy, PartialEq)]
@@ -16,38 +43,220 @@ pub enum Direction {
     Right,
 }
 
+impl Direction {
+    const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ];
+
+    fn offset(self) -> Point {
+        match self {
+            Direction::Up => Point(0, -1),
+            Direction::Down => Point(0, 1),
+            Direction::Left => Point(-1, 0),
+            Direction::Right => Point(1, 0),
+        }
+    }
+}
+
 pub struct Snake {
     pub body: VecDeque<Point>,
     pub head: Point,
-    pub old_tail: Point,
+    /// Team of the snake, 0 if it plays alone
+    pub team: u8,
+    pub score: u16,
+    /// Active power-up effects, and when they end
+    pub effects: HashMap<PowerUp, Instant>,
 }
 
 impl Snake {
-    pub fn new(body: VecDeque<Point>, head: Point) -> Self {
-        let old_tail = *body.front().unwrap();
-
+    pub fn new(body: VecDeque<Point>, head: Point, team: u8) -> Self {
         Self {
             body,
             head,
-            old_tail,
+            team,
+            score: 0,
+            effects: HashMap::new(),
         }
     }
 }
 
+pub struct Flag {
+    pub team: u8,
+    pub base: Point,
+    /// Snake carrying the flag on its head, 0 if none
+    pub carrier: u8,
+    /// Where the flag lies when nobody carries it
+    pub position: Point,
+}
+
 pub struct GameContext {
+    /// Our snake, 0 when spectating
     pub snake_id: u8,
+    /// Snake followed by a spectator, the whole arena is watched if none
+    pub spectating: Option<u8>,
     pub snakes: HashMap<u8, Snake>,
-    pub food: Point,
+    pub food: HashSet<Point>,
+    pub map: Map,
+    /// Cells outside of these are lethal
+    pub bounds: Bounds,
+    /// Score of each team, team 1 being the first
+    pub team_scores: Vec<u16>,
+    /// Flags of capture the flag, one per team
+    pub flags: Vec<Flag>,
+    pub power_ups: HashMap<Point, PowerUp>,
     pub state: State,
+    /// Round trip time to the server, once measured
+    pub ping: Option<Duration>,
+    /// When the server last sent anything, to notice a dead connection
+    pub last_received: Instant,
+    /// Token to reconnect with, and how long the server keeps our snake
+    pub session: Option<(u64, Duration)>,
+    /// Snapshots received lately, the server sends deltas against them
+    pub snapshots: SnapshotHistory,
+    /// Direction asked of the server that the snapshots do not show yet
+    pub turning: Option<Direction>,
+    pub chat: VecDeque<ChatLine>,
+    /// Message being typed, none if the chat box is closed
+    pub chat_input: Option<String>,
 }
 
 impl GameContext {
     pub fn new() -> Self {
         Self {
             snake_id: 0,
+            spectating: None,
             snakes: HashMap::new(),
-            food: Point(0, 0),
+            food: HashSet::new(),
+            map: Map::default(),
+            bounds: Map::default().bounds(),
+            team_scores: Vec::new(),
+            flags: Vec::new(),
+            power_ups: HashMap::new(),
             state: State::Joining,
+            ping: None,
+            last_received: Instant::now(),
+            session: None,
+            snapshots: SnapshotHistory::default(),
+            turning: None,
+            chat: VecDeque::new(),
+            chat_input: None,
+        }
+    }
+
+    pub fn is_spectator(&self) -> bool {
+        self.snake_id == 0
+    }
+
+    /// The snake the player looks at: its own, or the one it spectates
+    pub fn focus(&self) -> Option<u8> {
+        if !self.is_spectator() {
+            return Some(self.snake_id);
+        }
+
+        self.spectating.filter(|id| self.snakes.contains_key(id))
+    }
+
+    /// Follows the next (or previous) snake by ID, wrapping around
+    pub fn cycle_target(&mut self, forward: bool) {
+        let mut ids: Vec<u8> = self.snakes.keys().cloned().collect();
+        ids.sort_unstable();
+
+        if !forward {
+            ids.reverse();
+        }
+
+        let next = match self.focus() {
+            Some(current) => ids
+                .iter()
+                .find(|id| {
+                    if forward {
+                        **id > current
+                    } else {
+                        **id < current
+                    }
+                })
+                .or(ids.first()),
+            None => ids.first(),
+        };
+
+        self.spectating = next.cloned();
+    }
+
+    /// Moves the snakes to where a snapshot puts them, returns the snapshot to acknowledge,
+    /// none if its base is unknown
+    pub fn apply_snapshot(&mut self, update: &SnapshotUpdate) -> Option<u32> {
+        let base = match update.base {
+            Some(seq) => Some(self.snapshots.get(seq)?),
+            None => None,
+        };
+
+        let snapshot = Snapshot::apply(base, update)?;
+
+        self.snakes
+            .retain(|snake_id, _| snapshot.snakes.contains_key(snake_id));
+
+        for (snake_id, state) in snapshot.snakes.iter() {
+            let Some((head, body)) = state.cells.split_last() else {
+                continue;
+            };
+            let body: VecDeque<Point> = body.iter().cloned().collect();
+
+            match self.snakes.get_mut(snake_id) {
+                Some(snake) => {
+                    snake.body = body;
+                    snake.head = *head;
+                    snake.team = state.team;
+                }
+                None => {
+                    self.snakes
+                        .insert(*snake_id, Snake::new(body, *head, state.team));
+                }
+            }
+        }
+
+        // The server turned our snake
+        if let Some(snake) = self.snakes.get(&self.snake_id) {
+            if self.turning.is_some() && self.turning == self.heading(snake) {
+                self.turning = None;
+            }
+        }
+
+        let seq = snapshot.seq;
+        self.snapshots.push(snapshot);
+
+        Some(seq)
+    }
+
+    /// The way a snake moves, going by its last two cells
+    fn heading(&self, snake: &Snake) -> Option<Direction> {
+        let neck = *snake.body.back()?;
+
+        Direction::ALL
+            .into_iter()
+            .find(|direction| self.map.wrap(neck + direction.offset()) == snake.head)
+    }
+
+    /// Where the head of our snake goes on the next tick, around the edges if they wrap,
+    /// and whether the snake dies there. None when spectating.
+    pub fn predicted_head(&self) -> Option<(Point, bool)> {
+        let snake = self.snakes.get(&self.snake_id)?;
+        let direction = self.turning.or_else(|| self.heading(snake))?;
+        let head = self.map.wrap(snake.head + direction.offset());
+
+        let lethal =
+            !self.map.contains(&head) || !self.bounds.contains(&head) || self.map.is_wall(&head);
+
+        Some((head, lethal))
+    }
+
+    pub fn add_chat_line(&mut self, line: ChatLine) {
+        self.chat.push_back(line);
+
+        if self.chat.len() > MAX_CHAT_HISTORY {
+            self.chat.pop_front();
         }
     }
 }
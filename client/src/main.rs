@@ -1,91 +1,203 @@
+mod camera;
+mod discovery;
+mod font;
 mod game;
+mod menu;
 mod renderer;
+mod settings;
 
 use std::{
-    collections::VecDeque,
     env,
-    io::{ErrorKind, Read, Write},
+    io::{self, ErrorKind, Read, Write},
     net::TcpStream,
+    time::{Duration, Instant},
 };
 
 use common::{
-    packet::{PacketBuilder, PacketType, ReadablePacket},
+    frame::Reassembler,
+    heartbeat::{self, Clock, PING_INTERVAL},
+    map::{Bounds, EdgeMode},
+    packet::{
+        PacketBuilder, PacketType, ReadablePacket, CAP_COMPRESSION, CAP_PACKED_STEPS,
+        CAP_SNAPSHOTS, MAX_CHAT_LEN,
+    },
+    power_up::PowerUp,
+    shutdown::ShutdownNotice,
+    snapshot::{self, SnapshotUpdate},
     util::Point,
 };
-use game::{Direction, GameContext, Snake};
+use game::{ChatLine, Direction, Flag, GameContext};
+use menu::{JoinOptions, Menu, MenuAction};
 use renderer::{Renderer, WINDOW_HEIGHT, WINDOW_WIDTH};
-use sdl2::{event::Event, keyboard::Keycode};
+use sdl2::{
+    event::{Event, WindowEvent},
+    keyboard::Keycode,
+    EventPump,
+};
+use settings::Settings;
 
 use crate::game::State;
 
 const ADDR: &str = "127.0.0.1:14300";
+/// The server pings every couple of seconds, it is gone if it stays silent this long
+const SERVER_TIMEOUT: Duration = Duration::from_secs(15);
+/// Time between two attempts to get back on the server after losing the connection
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(2);
 
-fn read_snake(packet: &mut ReadablePacket) -> Snake {
-    let snake_sz = packet.read_u16_le() as usize;
+fn process_packet(packet: &mut ReadablePacket, context: &mut GameContext) {
+    match packet.r#type {
+        PacketType::MapData => {
+            let map = &mut context.map;
 
-    let mut body = VecDeque::with_capacity(snake_sz - 1);
+            map.width = packet.read() as i32;
+            map.height = packet.read() as i32;
+            map.edges = EdgeMode::from_u8(packet.read()).unwrap_or(EdgeMode::Wrap);
 
-    for _ in 0..snake_sz - 1 {
-        body.push_back(Point(packet.read() as i32, packet.read() as i32));
-    }
+            let wall_count = packet.read_u16_le();
 
-    let head = Point(packet.read() as i32, packet.read() as i32);
+            map.walls.clear();
 
-    Snake::new(body, head)
-}
+            for _ in 0..wall_count {
+                map.walls
+                    .insert(Point(packet.read() as i32, packet.read() as i32));
+            }
 
-fn process_packet(packet: &mut ReadablePacket, context: &mut GameContext) {
-    match packet.r#type {
-        PacketType::Info => {
-            context.snake_id = packet.read();
-            let mut obj_type = packet.read();
+            context.bounds = context.map.bounds();
+        }
+        PacketType::ArenaBounds => {
+            let bounds = Bounds {
+                min: Point(packet.read() as i32, packet.read() as i32),
+                max: Point(packet.read() as i32, packet.read() as i32),
+            };
 
-            while obj_type != 0xff {
-                // Read snakes
-                let snake = read_snake(packet);
+            context.food.retain(|food| bounds.contains(food));
+            context.bounds = bounds;
+        }
+        PacketType::Info => {
+            // The full state, also sent again when the server starts a new round.
+            // The snakes follow in a keyframe
+            context.snakes.clear();
+            context.food.clear();
+            context.turning = None;
 
-                context.snakes.insert(obj_type, snake);
+            context.snake_id = packet.read();
 
-                println!("INFO: Spawned snake {obj_type}");
+            let food_count = packet.read_u16_le();
 
-                obj_type = packet.read();
+            for _ in 0..food_count {
+                context
+                    .food
+                    .insert(Point(packet.read() as i32, packet.read() as i32));
             }
 
-            context.food = Point(packet.read() as i32, packet.read() as i32);
             context.state = State::Playing;
         }
         PacketType::FoodUpdate => {
-            let snake_id = packet.read();
-            let snake = context.snakes.get_mut(&snake_id).unwrap();
-
-            snake.body.push_front(snake.old_tail);
+            while packet.remaining() > 0 {
+                // Skips the snake that ate it, it grows in the next snapshots
+                packet.read();
+                let food = Point(packet.read() as i32, packet.read() as i32);
 
-            context.food = Point(packet.read() as i32, packet.read() as i32);
+                context.food.remove(&food);
+            }
         }
-        PacketType::HeadUpdate => {
+        PacketType::FoodSpawn => {
+            while packet.remaining() > 0 {
+                context
+                    .food
+                    .insert(Point(packet.read() as i32, packet.read() as i32));
+            }
+        }
+        PacketType::ScoreUpdate => {
+            let team_count = packet.read();
+
+            context.team_scores = (0..team_count).map(|_| packet.read_u16_le()).collect();
+
             while packet.remaining() > 0 {
                 let snake_id = packet.read();
+                let score = packet.read_u16_le();
 
-                let head = Point(packet.read() as i32, packet.read() as i32);
+                if let Some(snake) = context.snakes.get_mut(&snake_id) {
+                    snake.score = score;
+                }
+            }
+        }
+        PacketType::FlagUpdate => {
+            context.flags.clear();
+
+            while packet.remaining() > 0 {
+                context.flags.push(Flag {
+                    team: packet.read(),
+                    base: Point(packet.read() as i32, packet.read() as i32),
+                    carrier: packet.read(),
+                    position: Point(packet.read() as i32, packet.read() as i32),
+                });
+            }
+        }
+        PacketType::PowerUpUpdate => {
+            context.power_ups.clear();
+
+            while packet.remaining() > 0 {
+                let power_up = PowerUp::from_u8(packet.read());
+                let point = Point(packet.read() as i32, packet.read() as i32);
+
+                if let Some(power_up) = power_up {
+                    context.power_ups.insert(point, power_up);
+                }
+            }
+        }
+        PacketType::EffectStart => {
+            while packet.remaining() > 0 {
+                let snake_id = packet.read();
+                let power_up = PowerUp::from_u8(packet.read());
+                let duration = Duration::from_millis(packet.read_u16_le() as u64 * 100);
 
-                let snake = context.snakes.get_mut(&snake_id).unwrap();
+                if let (Some(snake), Some(power_up)) = (context.snakes.get_mut(&snake_id), power_up)
+                {
+                    snake.effects.insert(power_up, Instant::now() + duration);
+                }
+            }
+        }
+        PacketType::EffectEnd => {
+            while packet.remaining() > 0 {
+                let snake_id = packet.read();
+                let power_up = PowerUp::from_u8(packet.read());
 
-                snake.body.push_back(snake.head);
-                snake.head = head;
-                snake.old_tail = snake.body.pop_front().unwrap();
+                if let (Some(snake), Some(power_up)) = (context.snakes.get_mut(&snake_id), power_up)
+                {
+                    snake.effects.remove(&power_up);
+                }
             }
         }
-        PacketType::SnakeConnect => {
+        PacketType::ChatMessage => {
             let snake_id = packet.read();
-            let snake = read_snake(packet);
+            let sender = packet.read_str();
+            let message = packet.read_str();
+
+            println!("INFO: [chat] {sender}: {message}");
 
-            context.snakes.insert(snake_id, snake);
-            println!("INFO: Spawned a new snake {snake_id}");
+            context.add_chat_line(ChatLine {
+                snake_id,
+                sender,
+                message,
+                received: Instant::now(),
+            });
         }
         PacketType::SnakeDisconnect => {
             let snake_id = packet.read();
 
             context.snakes.remove(&snake_id);
+
+            // Our snake is gone for good, there is nothing to reconnect to
+            if snake_id == context.snake_id {
+                context.session = None;
+            }
+        }
+        PacketType::Session => {
+            let token = packet.read_u64_le();
+            let grace = Duration::from_secs(packet.read_u16_le() as u64);
+
+            context.session = Some((token, grace));
         }
         _ => {
             eprintln!("WARN: Received unknown packet!");
@@ -93,92 +205,411 @@ fn process_packet(packet: &mut ReadablePacket, context: &mut GameContext) {
     }
 }
 
-fn read_packets(stream: &mut TcpStream, context: &mut GameContext) -> bool {
-    let mut size_bytes = [0u8; 2];
+/// Processes every complete packet received so far, without blocking.
+/// Returns false once the connection is over, the state telling why.
+fn read_packets(
+    stream: &mut TcpStream,
+    buffer: &mut Vec<u8>,
+    fragments: &mut Reassembler,
+    clock: &Clock,
+    context: &mut GameContext,
+) -> bool {
+    let mut chunk = [0u8; 4096];
+    let mut closed = None;
+
+    loop {
+        match stream.read(&mut chunk) {
+            Ok(0) => {
+                println!("INFO: Disconnected");
+                closed = Some("Connection closed by the server".to_string());
+                break;
+            }
+            Ok(read) => {
+                buffer.extend_from_slice(&chunk[..read]);
+                context.last_received = Instant::now();
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => {
+                eprintln!("Error reading the TCP stream {err}");
+                closed = Some(err.to_string());
+                break;
+            }
+        }
+    }
+
+    // Whatever arrived before the connection closed still counts
+    while buffer.len() >= 2 {
+        let packet_size = u16::from_le_bytes([buffer[0], buffer[1]]) as usize;
 
-    if let Err(err) = stream.read_exact(&mut size_bytes) {
-        if err.kind() != ErrorKind::UnexpectedEof {
-            eprintln!("Error reading the TCP stream {err}");
+        if packet_size == 0 {
+            eprintln!("WARN: Received a empty packet");
+            context.state = State::Disconnected("Received an invalid packet".to_string());
+            return false;
         }
 
-        println!("INFO: Disconnected");
-        return false;
-    }
+        if buffer.len() < 2 + packet_size {
+            break;
+        }
+
+        let frame: Vec<u8> = buffer.drain(..2 + packet_size).skip(2).collect();
+
+        // Large packets come in several fragments
+        let Some(frame) = fragments.push(frame) else {
+            continue;
+        };
+
+        let Some(mut packet) = ReadablePacket::try_from_server_bytes(&frame) else {
+            eprintln!("WARN: Received an unknown or corrupted packet");
+            context.state = State::Disconnected("Received an invalid packet".to_string());
+            return false;
+        };
+
+        if packet.r#type == PacketType::ConnRejected {
+            // Older servers don't send a reason
+            let reason = packet
+                .try_read_str()
+                .unwrap_or_else(|| "Server full".to_string());
+
+            println!("INFO: Connection rejected: {reason}");
+            context.state = State::Rejected(reason);
+            return false;
+        }
+
+        if let Some(notice) = ShutdownNotice::from_packet(&mut packet) {
+            println!("INFO: The server shut down: {}", notice.reason);
+
+            let reason = match notice.restart_in {
+                Some(eta) if eta.as_secs() >= 120 => {
+                    format!(
+                        "{}, back in about {} minutes",
+                        notice.reason,
+                        eta.as_secs() / 60
+                    )
+                }
+                Some(eta) => format!("{}, back in about {} seconds", notice.reason, eta.as_secs()),
+                None => notice.reason,
+            };
+
+            context.state = State::ServerClosed(reason);
+            return false;
+        }
+
+        match packet.r#type {
+            PacketType::Ping => {
+                if let Some(timestamp) = heartbeat::read_timestamp(&mut packet) {
+                    let _ = stream.write_all(&heartbeat::pong_packet(timestamp));
+                }
+            }
+            PacketType::Snapshot => {
+                let acked = SnapshotUpdate::from_packet(&mut packet)
+                    .and_then(|update| context.apply_snapshot(&update));
 
-    let packet_size = u16::from_le_bytes(size_bytes) as usize;
+                // Acknowledging 0 asks for a keyframe, when the base of a delta is unknown
+                let _ = stream.write_all(&snapshot::ack_packet(acked.unwrap_or(0)));
+            }
+            PacketType::Pong => {
+                context.ping =
+                    heartbeat::read_timestamp(&mut packet).map(|timestamp| clock.since(timestamp));
+            }
+            _ => process_packet(&mut packet, context),
+        }
+    }
 
-    if packet_size == 0 {
-        eprintln!("WARN: Received a empty packet");
+    if let Some(reason) = closed {
+        context.state = State::Disconnected(reason);
         return false;
     }
 
-    let mut buffer = vec![0; packet_size];
+    true
+}
+
+/// Sends a packet, dropping the connection if that fails
+fn send_packet(stream: &mut Option<TcpStream>, packet: PacketBuilder, context: &mut GameContext) {
+    let Some(tcp) = stream.as_mut() else {
+        return;
+    };
+
+    if let Err(err) = tcp.write_all(&packet.build()) {
+        eprintln!("ERROR: Could not send TCP packet: {err}");
+        context.state = State::Disconnected(err.to_string());
+        *stream = None;
+    }
+}
 
-    if let Err(err) = stream.read_exact(&mut buffer) {
-        eprintln!("Error reading the TCP stream (buffer) {err}");
+/// Types in the open chat box, returns whether the event was used
+fn handle_chat_event(
+    event: &Event,
+    stream: &mut Option<TcpStream>,
+    context: &mut GameContext,
+) -> bool {
+    let Some(input) = context.chat_input.as_mut() else {
         return false;
+    };
+
+    match event {
+        Event::TextInput { text, .. } => {
+            for c in text.chars().filter(|c| !c.is_control()) {
+                if input.chars().count() < MAX_CHAT_LEN {
+                    input.push(c);
+                }
+            }
+        }
+        Event::KeyDown {
+            keycode: Some(keycode),
+            ..
+        } => match keycode {
+            Keycode::Backspace => {
+                input.pop();
+            }
+            Keycode::Return | Keycode::KpEnter => {
+                let message = std::mem::take(input);
+                context.chat_input = None;
+
+                if !message.trim().is_empty() {
+                    let mut packet =
+                        PacketBuilder::with_capacity(PacketType::ChatSend, 1 + message.len());
+                    packet.write_str(&message);
+
+                    send_packet(stream, packet, context);
+                }
+            }
+            Keycode::Escape => context.chat_input = None,
+            // The game keys are for typing while the chat box is open
+            _ => {}
+        },
+        _ => return false,
     }
 
-    let mut packet = ReadablePacket::from_bytes(&buffer);
+    true
+}
 
-    if packet.r#type == PacketType::ConnRejected {
-        println!("INFO: Server full!");
-        return false;
+/// Handles the events shared by every screen, returns whether the event was used
+fn handle_window_event(
+    event: &Event,
+    renderer: &mut Renderer,
+    settings: &mut Settings,
+) -> Result<bool, ()> {
+    match event {
+        Event::Window {
+            win_event: WindowEvent::SizeChanged(..),
+            ..
+        } => renderer.resize().map_err(|err| {
+            eprintln!("ERROR: Could not resize the window: {err}");
+        })?,
+        Event::KeyDown {
+            keycode: Some(Keycode::F11),
+            repeat: false,
+            ..
+        } => {
+            settings.fullscreen = !settings.fullscreen;
+            settings.save();
+        }
+        _ => return Ok(false),
     }
 
-    process_packet(&mut packet, context);
+    Ok(true)
+}
 
-    true
+/// Shows the start menu until the player picks a server, none if they quit
+fn run_menu(
+    menu: &mut Menu,
+    settings: &mut Settings,
+    renderer: &mut Renderer,
+    event_pump: &mut EventPump,
+) -> Result<Option<JoinOptions>, ()> {
+    loop {
+        for event in event_pump.poll_iter() {
+            if let Event::Quit { .. } = event {
+                return Ok(None);
+            }
+
+            if handle_window_event(&event, renderer, settings)? {
+                continue;
+            }
+
+            match menu.handle_event(&event, settings) {
+                Some(MenuAction::Join(options)) => return Ok(Some(options)),
+                Some(MenuAction::Quit) => return Ok(None),
+                None => {}
+            }
+        }
+
+        menu.update();
+
+        renderer.apply_settings(settings).map_err(|err| {
+            eprintln!("ERROR: Could not apply the settings: {err}");
+        })?;
+
+        renderer.render_menu(menu, settings).map_err(|err| {
+            eprintln!("ERROR: Could not render a frame: {err}");
+        })?;
+    }
 }
 
-fn main() -> Result<(), ()> {
-    let args: Vec<_> = env::args().collect();
-    let server_addr = if args.len() >= 2 {
-        &args[1]
-    } else {
-        println!("INFO: Server IP not provided, falling back to {ADDR}");
-        ADDR
-    };
+/// Connects to the server and asks to join, resuming a session if there is one
+fn connect(options: &JoinOptions, session: Option<u64>) -> io::Result<TcpStream> {
+    let mut stream = TcpStream::connect(&options.address)?;
+    println!("INFO: TCP Socket connected to {}", options.address);
 
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
+    let mut packet = PacketBuilder::with_capacity(PacketType::Join, 12 + options.nickname.len());
+    packet.write(options.team);
+    packet.write(options.spectate as u8);
+    packet.write_str(&options.nickname);
+    // 0 stands for no session
+    packet.write_u64_le(session.unwrap_or(0));
+    packet.write(CAP_SNAPSHOTS | CAP_PACKED_STEPS | CAP_COMPRESSION);
 
-    let window = video_subsystem
-        .window("Snake Multiplayer", WINDOW_WIDTH, WINDOW_HEIGHT)
-        .position_centered()
-        .build()
-        .unwrap();
+    stream.write_all(&packet.build())?;
+    stream.set_nonblocking(true)?;
 
-    let mut renderer = Renderer::new(window).map_err(|err| {
-        eprintln!("ERROR: Could not create the renderer: {err}");
-    })?;
+    Ok(stream)
+}
+
+/// Plays on a server, returns whether the player went back to the menu rather than quit
+fn play(
+    options: &JoinOptions,
+    settings: &mut Settings,
+    renderer: &mut Renderer,
+    event_pump: &mut EventPump,
+) -> Result<bool, ()> {
+    let server_addr = &options.address;
     let mut context = GameContext::new();
 
-    let mut event_pump = sdl_context.event_pump().unwrap();
+    // No stream once disconnected, the reason stays on screen until the player leaves
+    let mut stream = match connect(options, None) {
+        Ok(stream) => Some(stream),
+        Err(err) => {
+            eprintln!("ERROR: Could not connect to the snake server: {err}");
+            context.state =
+                State::Disconnected(format!("Could not connect to {server_addr}: {err}"));
+            None
+        }
+    };
+    let mut buffer = Vec::new();
+    let mut fragments = Reassembler::default();
 
-    let mut stream = TcpStream::connect(server_addr).map_err(|err| {
-        eprintln!("ERROR: Could not connect to the snake server: {err}");
-    })?;
+    let clock = Clock::start();
+    let mut last_ping = Instant::now();
 
-    println!("INFO: TCP Socket connected to {server_addr}");
+    // Until when the server keeps our snake, and when to try again
+    let mut reconnect_deadline = None;
+    let mut last_attempt = Instant::now();
 
     let mut old_dir = Direction::Right;
     let mut next_direction = Direction::Right;
+    let mut old_boosting = false;
+    let mut boosting = false;
+
+    loop {
+        if let Some(tcp) = stream.as_mut() {
+            if !read_packets(tcp, &mut buffer, &mut fragments, &clock, &mut context) {
+                stream = None;
+            }
+        }
+
+        if stream.is_some() && context.last_received.elapsed() > SERVER_TIMEOUT {
+            eprintln!("ERROR: The server stopped responding");
+            context.state = State::Disconnected("The server stopped responding".to_string());
+            stream = None;
+        }
+
+        if context.state == State::Playing {
+            reconnect_deadline = None;
+        }
+
+        // A lost connection is retried while the server keeps our snake
+        if let (State::Disconnected(reason), Some((_, grace))) = (&context.state, context.session) {
+            let deadline = *reconnect_deadline.get_or_insert_with(|| Instant::now() + grace);
+
+            if Instant::now() < deadline {
+                println!("INFO: Connection lost, reconnecting");
+                context.state = State::Reconnecting(reason.clone());
+                last_attempt = Instant::now();
+            }
+        }
 
-    'running: loop {
-        let res = read_packets(&mut stream, &mut context);
+        if let State::Reconnecting(reason) = &context.state {
+            if reconnect_deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                eprintln!("ERROR: Could not reconnect in time");
+                context.state = State::Disconnected(reason.clone());
+                context.session = None;
+            } else if stream.is_none() && last_attempt.elapsed() >= RECONNECT_INTERVAL {
+                last_attempt = Instant::now();
+
+                match connect(options, context.session.map(|(token, _)| token)) {
+                    Ok(tcp) => {
+                        buffer.clear();
+                        fragments = Reassembler::default();
+                        context.last_received = Instant::now();
+                        stream = Some(tcp);
+                    }
+                    Err(err) => eprintln!("WARN: Could not reconnect: {err}"),
+                }
+            }
+        }
+
+        if last_ping.elapsed() >= PING_INTERVAL {
+            last_ping = Instant::now();
+
+            let mut packet = PacketBuilder::with_capacity(PacketType::Ping, 4);
+            packet.write_u32_le(clock.timestamp());
 
-        if !res {
-            break 'running;
+            send_packet(&mut stream, packet, &mut context);
         }
 
         for event in event_pump.poll_iter() {
+            if handle_window_event(&event, renderer, settings)?
+                || handle_chat_event(&event, &mut stream, &mut context)
+            {
+                continue;
+            }
+
             match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
+                Event::Quit { .. } => return Ok(false),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Return | Keycode::KpEnter),
+                    repeat: false,
+                    ..
+                } if context.state == State::Playing => context.chat_input = Some(String::new()),
+                Event::KeyDown {
                     keycode: Some(Keycode::Escape),
                     ..
-                } => break 'running,
+                } => return Ok(true),
+                Event::KeyDown {
+                    keycode:
+                        Some(
+                            keycode @ (Keycode::Equals
+                            | Keycode::Plus
+                            | Keycode::KpPlus
+                            | Keycode::Minus
+                            | Keycode::KpMinus),
+                        ),
+                    ..
+                } => renderer.camera.zoom(matches!(
+                    keycode,
+                    Keycode::Equals | Keycode::Plus | Keycode::KpPlus
+                )),
+                Event::MouseWheel { y, .. } if y != 0 => renderer.camera.zoom(y > 0),
+                // Spectators cycle through the snakes or watch the whole arena
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } if context.is_spectator() => match keycode {
+                    Keycode::D | Keycode::Right | Keycode::Tab => context.cycle_target(true),
+                    Keycode::A | Keycode::Left => context.cycle_target(false),
+                    Keycode::Space => context.spectating = None,
+                    _ => {}
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::Space),
+                    repeat: false,
+                    ..
+                } => boosting = true,
+                Event::KeyUp {
+                    keycode: Some(Keycode::Space),
+                    ..
+                } => boosting = false,
                 Event::KeyDown {
                     keycode: Some(keycode),
                     ..
@@ -213,15 +644,82 @@ fn main() -> Result<(), ()> {
             let mut packet = PacketBuilder::with_capacity(PacketType::DirectionUpdate, 1);
             packet.write(dir);
 
-            stream.write_all(&packet.build()).map_err(|err| {
-                eprintln!("ERROR: Could not send TCP packet: {err}");
-            })?;
+            send_packet(&mut stream, packet, &mut context);
+            context.turning = Some(next_direction);
+        }
+
+        if old_boosting != boosting {
+            old_boosting = boosting;
+
+            let mut packet = PacketBuilder::with_capacity(PacketType::Boost, 1);
+            packet.write(boosting as u8);
+
+            send_packet(&mut stream, packet, &mut context);
         }
 
+        renderer.apply_settings(settings).map_err(|err| {
+            eprintln!("ERROR: Could not apply the settings: {err}");
+        })?;
+
         renderer.render(&context).map_err(|err| {
             eprintln!("ERROR: Could not render a frame: {err}");
         })?;
     }
+}
+
+fn main() -> Result<(), ()> {
+    let args: Vec<_> = env::args().collect();
+    let mut settings = Settings::load();
+
+    // A server given on the command line is joined right away, skipping the menu
+    let mut join = args.get(1).map(|address| JoinOptions {
+        address: address.clone(),
+        nickname: settings.nickname.clone(),
+        // 0 lets the server pick the team with the fewest players
+        team: args.get(2).and_then(|team| team.parse().ok()).unwrap_or(0),
+        spectate: args.get(2).is_some_and(|arg| arg == "spectate"),
+    });
+
+    let sdl_context = sdl2::init().unwrap();
+    let video_subsystem = sdl_context.video().unwrap();
+
+    let window = video_subsystem
+        .window("Snake Multiplayer", WINDOW_WIDTH, WINDOW_HEIGHT)
+        .position_centered()
+        .resizable()
+        .allow_highdpi()
+        .build()
+        .unwrap();
+
+    let mut renderer = Renderer::new(window).map_err(|err| {
+        eprintln!("ERROR: Could not create the renderer: {err}");
+    })?;
+
+    let mut event_pump = sdl_context.event_pump().unwrap();
+
+    let address = settings
+        .recent_servers
+        .first()
+        .cloned()
+        .unwrap_or(ADDR.to_string());
+    let mut menu = Menu::new(address);
+
+    loop {
+        let options = match join.take() {
+            Some(options) => options,
+            None => match run_menu(&mut menu, &mut settings, &mut renderer, &mut event_pump)? {
+                Some(options) => options,
+                None => break,
+            },
+        };
+
+        settings.add_recent_server(&options.address);
+        settings.save();
+
+        if !play(&options, &mut settings, &mut renderer, &mut event_pump)? {
+            break;
+        }
+    }
 
     Ok(())
 }
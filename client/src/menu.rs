@@ -0,0 +1,303 @@
+use common::packet::MAX_NICKNAME_LEN;
+use sdl2::{event::Event, keyboard::Keycode};
+
+use crate::{discovery::Discovery, settings::Settings};
+
+/// Longest server address that can be typed
+const MAX_ADDRESS_LEN: usize = 64;
+/// Highest team that can be picked, the server ignores teams it doesn't have
+const MAX_TEAM: u8 = 4;
+
+/// What the player picked to join a game
+pub struct JoinOptions {
+    pub address: String,
+    pub nickname: String,
+    /// Preferred team, 0 to let the server pick
+    pub team: u8,
+    pub spectate: bool,
+}
+
+pub enum MenuAction {
+    Join(JoinOptions),
+    Quit,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Screen {
+    Main,
+    /// Servers found on the LAN
+    Browser,
+    Settings,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Item {
+    Address,
+    Nickname,
+    Team,
+    Spectate,
+    Connect,
+    RecentServer(usize),
+    Browse,
+    Server(usize),
+    Refresh,
+    Settings,
+    Quit,
+    Fullscreen,
+    Minimap,
+    Hud,
+    Back,
+}
+
+/// A line of the menu, as drawn by the renderer
+pub struct MenuEntry {
+    pub text: String,
+    pub selected: bool,
+    /// Text field being typed in
+    pub editing: bool,
+}
+
+/// The start menu, the LAN server browser and the settings screen, navigated with the keyboard
+pub struct Menu {
+    pub screen: Screen,
+    selected: usize,
+    address: String,
+    team: u8,
+    spectate: bool,
+    discovery: Discovery,
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "On"
+    } else {
+        "Off"
+    }
+}
+
+impl Menu {
+    pub fn new(address: String) -> Self {
+        Self {
+            screen: Screen::Main,
+            selected: 0,
+            address,
+            team: 0,
+            spectate: false,
+            discovery: Discovery::new(),
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self.screen {
+            Screen::Main => "Snake Multiplayer",
+            Screen::Browser => "LAN servers",
+            Screen::Settings => "Settings",
+        }
+    }
+
+    fn items(&self, settings: &Settings) -> Vec<Item> {
+        match self.screen {
+            Screen::Main => {
+                let mut items = vec![
+                    Item::Address,
+                    Item::Nickname,
+                    Item::Team,
+                    Item::Spectate,
+                    Item::Connect,
+                ];
+
+                items.extend((0..settings.recent_servers.len()).map(Item::RecentServer));
+                items.extend([Item::Browse, Item::Settings, Item::Quit]);
+
+                items
+            }
+            Screen::Browser => {
+                let mut items: Vec<_> = (0..self.discovery.servers().len())
+                    .map(Item::Server)
+                    .collect();
+
+                items.extend([Item::Refresh, Item::Back]);
+
+                items
+            }
+            Screen::Settings => vec![Item::Fullscreen, Item::Minimap, Item::Hud, Item::Back],
+        }
+    }
+
+    fn label(&self, item: Item, settings: &Settings) -> String {
+        match item {
+            Item::Address => format!("Server: {}", self.address),
+            Item::Nickname => format!("Nickname: {}", settings.nickname),
+            Item::Team if self.team == 0 => "Team: Auto".to_string(),
+            Item::Team => format!("Team: {}", self.team),
+            Item::Spectate => format!("Spectate: {}", on_off(self.spectate)),
+            Item::Connect => "Connect".to_string(),
+            Item::RecentServer(i) => format!("Recent: {}", settings.recent_servers[i]),
+            Item::Browse => "LAN servers".to_string(),
+            Item::Server(i) => {
+                let server = &self.discovery.servers()[i];
+                let info = &server.info;
+
+                format!(
+                    "{} {}/{} {} - {}",
+                    info.name,
+                    info.players,
+                    info.max_players,
+                    info.mode.name(),
+                    server.address
+                )
+            }
+            Item::Refresh => format!("Refresh ({} found)", self.discovery.servers().len()),
+            Item::Settings => "Settings".to_string(),
+            Item::Quit => "Quit".to_string(),
+            Item::Fullscreen => format!("Fullscreen: {}", on_off(settings.fullscreen)),
+            Item::Minimap => format!("Minimap: {}", on_off(settings.minimap)),
+            Item::Hud => format!("HUD: {}", on_off(settings.hud)),
+            Item::Back => "Back".to_string(),
+        }
+    }
+
+    pub fn entries(&self, settings: &Settings) -> Vec<MenuEntry> {
+        self.items(settings)
+            .into_iter()
+            .enumerate()
+            .map(|(i, item)| MenuEntry {
+                text: self.label(item, settings),
+                selected: i == self.selected,
+                editing: i == self.selected && matches!(item, Item::Address | Item::Nickname),
+            })
+            .collect()
+    }
+
+    fn open(&mut self, screen: Screen) {
+        self.screen = screen;
+        self.selected = 0;
+
+        if screen == Screen::Browser {
+            self.discovery.probe();
+        }
+    }
+
+    /// Picks up the servers that answered, called every frame
+    pub fn update(&mut self) {
+        if self.screen == Screen::Browser {
+            self.discovery.poll();
+        }
+    }
+
+    fn join(&self, address: &str, settings: &Settings) -> Option<MenuAction> {
+        let address = address.trim();
+
+        if address.is_empty() {
+            return None;
+        }
+
+        Some(MenuAction::Join(JoinOptions {
+            address: address.to_string(),
+            nickname: settings.nickname.clone(),
+            team: self.team,
+            spectate: self.spectate,
+        }))
+    }
+
+    /// Changes the value of an option, `forward` being false when going left
+    fn change(&mut self, item: Item, forward: bool, settings: &mut Settings) {
+        match item {
+            Item::Team if forward => self.team = (self.team + 1) % (MAX_TEAM + 1),
+            Item::Team => self.team = (self.team + MAX_TEAM) % (MAX_TEAM + 1),
+            Item::Spectate => self.spectate = !self.spectate,
+            Item::Fullscreen => settings.fullscreen = !settings.fullscreen,
+            Item::Minimap => settings.minimap = !settings.minimap,
+            Item::Hud => settings.hud = !settings.hud,
+            _ => {}
+        }
+    }
+
+    fn activate(&mut self, item: Item, settings: &mut Settings) -> Option<MenuAction> {
+        match item {
+            Item::Address | Item::Nickname | Item::Connect => self.join(&self.address, settings),
+            Item::RecentServer(i) => {
+                self.address = settings.recent_servers[i].clone();
+                self.join(&self.address, settings)
+            }
+            Item::Browse => {
+                self.open(Screen::Browser);
+                None
+            }
+            Item::Server(i) => {
+                self.address = self.discovery.servers()[i].address.clone();
+                self.join(&self.address, settings)
+            }
+            Item::Refresh => {
+                self.discovery.probe();
+                None
+            }
+            Item::Settings => {
+                self.open(Screen::Settings);
+                None
+            }
+            Item::Quit => Some(MenuAction::Quit),
+            Item::Back => {
+                settings.save();
+                self.open(Screen::Main);
+                None
+            }
+            item => {
+                self.change(item, true, settings);
+                None
+            }
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event, settings: &mut Settings) -> Option<MenuAction> {
+        let items = self.items(settings);
+        // The recent servers may have changed since the last frame
+        self.selected = self.selected.min(items.len() - 1);
+        let item = items[self.selected];
+
+        match event {
+            Event::TextInput { text, .. } => {
+                let (field, max_len) = match item {
+                    Item::Address => (&mut self.address, MAX_ADDRESS_LEN),
+                    Item::Nickname => (&mut settings.nickname, MAX_NICKNAME_LEN),
+                    _ => return None,
+                };
+
+                for c in text.chars().filter(|c| !c.is_control()) {
+                    if field.chars().count() < max_len {
+                        field.push(c);
+                    }
+                }
+            }
+            Event::KeyDown {
+                keycode: Some(keycode),
+                ..
+            } => match keycode {
+                Keycode::Up => self.selected = (self.selected + items.len() - 1) % items.len(),
+                Keycode::Down | Keycode::Tab => self.selected = (self.selected + 1) % items.len(),
+                Keycode::Left => self.change(item, false, settings),
+                Keycode::Right => self.change(item, true, settings),
+                Keycode::Return | Keycode::KpEnter => return self.activate(item, settings),
+                Keycode::Backspace => match item {
+                    Item::Address => {
+                        self.address.pop();
+                    }
+                    Item::Nickname => {
+                        settings.nickname.pop();
+                    }
+                    _ => {}
+                },
+                Keycode::Escape => match self.screen {
+                    Screen::Main => return Some(MenuAction::Quit),
+                    Screen::Browser | Screen::Settings => {
+                        return self.activate(Item::Back, settings)
+                    }
+                },
+                _ => {}
+            },
+            _ => {}
+        }
+
+        None
+    }
+}
@@ -1,71 +1,759 @@
-use common::util::Point;
-use sdl2::{pixels::Color, rect::Rect, render::WindowCanvas, video::Window};
+use std::time::{Duration, Instant};
 
-use crate::game::GameContext;
+use common::{
+    map::{Bounds, EdgeMode},
+    power_up::{PowerUp, MAGNET_RADIUS},
+    util::Point,
+};
+use sdl2::{
+    pixels::Color,
+    rect::Rect,
+    render::{BlendMode, WindowCanvas},
+    video::{FullscreenType, Window},
+};
+
+use crate::{
+    camera::Camera,
+    font::{glyph, GLYPH_HEIGHT, GLYPH_SPACING, GLYPH_WIDTH},
+    game::{GameContext, State},
+    menu::Menu,
+    settings::Settings,
+};
 
 const BG_COLOR: Color = Color::RGB(24, 24, 24);
+/// Bars around an arena that doesn't fill the window
+const LETTERBOX_COLOR: Color = Color::BLACK;
 const FOOD_COLOR: Color = Color::RED;
+const WALL_COLOR: Color = Color::RGB(90, 90, 90);
+const BORDER_COLOR: Color = Color::RGB(200, 40, 40);
+/// Outlines the cell our snake moves into next
+const PREDICTION_COLOR: Color = Color::RGBA(255, 255, 255, 90);
+const CLOSED_ZONE_COLOR: Color = Color::RGBA(200, 40, 40, 60);
+const FLAG_OUTLINE_COLOR: Color = Color::WHITE;
+/// Alpha of the team colour filling a base
+const BASE_ALPHA: u8 = 70;
+const INVINCIBLE_COLOR: Color = Color::RGB(255, 215, 0);
+/// Alpha of a ghost snake
+const GHOST_ALPHA: u8 = 90;
+const MAGNET_COLOR: Color = Color::RGBA(255, 0, 255, 90);
+const SHIELD_COLOR: Color = Color::WHITE;
+/// Effects blink for this long before they end
+const EFFECT_BLINK_TIME: Duration = Duration::from_millis(1500);
+const EFFECT_BLINK_PERIOD: u128 = 150;
 const SNAKE_COLOR: Color = Color::BLUE;
 const SNAKE_HEAD_COLOR: Color = Color::CYAN;
 const ENEMY_BODY_COLOR: Color = Color::RGB(255, 100, 0);
 const ENEMY_HEAD_COLOR: Color = Color::YELLOW;
+/// Body and head colours of each team, team 1 being the first
+const TEAM_COLORS: [(Color, Color); 4] = [
+    (Color::RGB(230, 120, 20), Color::RGB(255, 200, 120)),
+    (Color::RGB(50, 90, 230), Color::RGB(150, 180, 255)),
+    (Color::RGB(40, 170, 60), Color::RGB(150, 240, 150)),
+    (Color::RGB(150, 60, 200), Color::RGB(220, 160, 255)),
+];
+
+const MINIMAP_BG_COLOR: Color = Color::RGBA(0, 0, 0, 180);
+const MINIMAP_BORDER_COLOR: Color = Color::RGB(120, 120, 120);
+const MINIMAP_VIEW_COLOR: Color = Color::WHITE;
+/// Largest side of the minimap, in logical pixels
+const MINIMAP_SIZE: f32 = 160.0;
+const MINIMAP_MARGIN: f32 = 10.0;
+
+const TEXT_COLOR: Color = Color::WHITE;
+const SELECTED_TEXT_COLOR: Color = Color::CYAN;
+const TITLE_COLOR: Color = Color::RGB(40, 200, 80);
+const TEXT_SHADOW_COLOR: Color = Color::RGBA(0, 0, 0, 200);
+const ERROR_COLOR: Color = Color::RGB(255, 90, 90);
+/// Dims the game behind status messages
+const OVERLAY_COLOR: Color = Color::RGBA(0, 0, 0, 170);
+/// Size of a font pixel, in logical pixels
+const HUD_TEXT_SCALE: f32 = 2.0;
+const TITLE_TEXT_SCALE: f32 = 4.0;
+const HUD_MARGIN: f32 = 10.0;
+/// Blinking period of the text cursor, in milliseconds
+const CURSOR_BLINK_PERIOD: u128 = 500;
+const SERVER_CHAT_COLOR: Color = Color::RGB(255, 120, 255);
+/// Chat messages shown at once
+const CHAT_LINES: usize = 6;
+/// Chat messages stay this long, then fade out, unless the chat box is open
+const CHAT_VISIBLE_TIME: Duration = Duration::from_secs(8);
+const CHAT_FADE_TIME: Duration = Duration::from_secs(2);
 
 pub const WINDOW_WIDTH: u32 = 800;
 pub const WINDOW_HEIGHT: u32 = 600;
-pub const SCALE: u32 = 10;
+
+fn power_up_color(power_up: PowerUp) -> Color {
+    match power_up {
+        PowerUp::Invincibility => INVINCIBLE_COLOR,
+        PowerUp::Ghost => Color::RGB(200, 200, 255),
+        PowerUp::Magnet => Color::MAGENTA,
+        PowerUp::Shield => SHIELD_COLOR,
+    }
+}
+
+/// Body and head colours of a team, none if the snake plays alone
+fn team_colors(team: u8) -> Option<&'static (Color, Color)> {
+    TEAM_COLORS.get((team as usize).wrapping_sub(1))
+}
+
+/// Body and head colours of a snake
+fn snake_colors(team: u8, is_me: bool) -> (Color, Color) {
+    // In team modes our own snake is told apart by its head only
+    match team_colors(team) {
+        Some((body, _)) if is_me => (*body, SNAKE_HEAD_COLOR),
+        Some(team_colors) => *team_colors,
+        None if is_me => (SNAKE_COLOR, SNAKE_HEAD_COLOR),
+        None => (ENEMY_BODY_COLOR, ENEMY_HEAD_COLOR),
+    }
+}
 
 pub struct Renderer {
     canvas: WindowCanvas,
+    pub camera: Camera,
+    last_frame: Instant,
+    /// Size of the drawable area, in pixels
+    screen: (u32, u32),
+    /// Pixels per logical window pixel, above 1 on high DPI displays
+    dpi_scale: f32,
+    /// Arena size along the axes drawn repeatedly because they wrap around, 0 if not
+    repeat: (i32, i32),
+    show_minimap: bool,
+    show_hud: bool,
+    /// When the renderer was created, for blinking
+    start: Instant,
 }
 
 impl Renderer {
     pub fn new(window: Window) -> Result<Renderer, String> {
-        let canvas = window
+        let mut canvas = window
             .into_canvas()
             .accelerated()
             .present_vsync()
             .build()
             .map_err(|e| e.to_string())?;
 
-        Ok(Renderer { canvas })
+        canvas.set_blend_mode(BlendMode::Blend);
+
+        let mut renderer = Renderer {
+            canvas,
+            camera: Camera::new(),
+            last_frame: Instant::now(),
+            screen: (0, 0),
+            dpi_scale: 1.0,
+            repeat: (0, 0),
+            show_minimap: true,
+            show_hud: true,
+            start: Instant::now(),
+        };
+
+        renderer.resize()?;
+
+        Ok(renderer)
+    }
+
+    /// Picks up the new drawable size after the window was resized
+    pub fn resize(&mut self) -> Result<(), String> {
+        self.screen = self.canvas.output_size()?;
+
+        let (window_width, _) = self.canvas.window().size();
+        self.dpi_scale = self.screen.0 as f32 / window_width.max(1) as f32;
+
+        Ok(())
+    }
+
+    pub fn apply_settings(&mut self, settings: &Settings) -> Result<(), String> {
+        self.show_minimap = settings.minimap;
+        self.show_hud = settings.hud;
+
+        let window = self.canvas.window_mut();
+        let fullscreen = window.fullscreen_state() != FullscreenType::Off;
+
+        if fullscreen != settings.fullscreen {
+            window.set_fullscreen(if settings.fullscreen {
+                FullscreenType::Desktop
+            } else {
+                FullscreenType::Off
+            })?;
+
+            self.resize()?;
+        }
+
+        Ok(())
+    }
+
+    /// On screen rectangles of the cells from `from` up to `to`, excluded,
+    /// once for every visible copy of a wrapping arena
+    fn screen_rects(&self, from: Point, to: Point) -> Vec<Rect> {
+        let Camera {
+            center, cell_size, ..
+        } = self.camera;
+        let (width, height) = self.screen;
+
+        let to_screen = |value: i32, center: f32, screen: u32| {
+            ((value as f32 - center) * cell_size + screen as f32 / 2.0).round() as i32
+        };
+        let copies = |size: i32| {
+            if size > 0 {
+                vec![-size, 0, size]
+            } else {
+                vec![0]
+            }
+        };
+
+        let viewport = Rect::new(0, 0, width, height);
+        let mut rects = Vec::new();
+
+        for dx in copies(self.repeat.0) {
+            for dy in copies(self.repeat.1) {
+                let x = to_screen(from.0 + dx, center.0, width);
+                let y = to_screen(from.1 + dy, center.1, height);
+                let rect = Rect::new(
+                    x,
+                    y,
+                    (to_screen(to.0 + dx, center.0, width) - x) as u32,
+                    (to_screen(to.1 + dy, center.1, height) - y) as u32,
+                );
+
+                if rect.has_intersection(viewport) {
+                    rects.push(rect);
+                }
+            }
+        }
+
+        rects
     }
 
     pub fn draw_point(&mut self, point: &Point) -> Result<(), String> {
-        let Point(x, y) = point;
+        self.draw_area(*point, *point + Point(1, 1))
+    }
+
+    pub fn outline_point(&mut self, point: &Point) -> Result<(), String> {
+        for rect in self.screen_rects(*point, *point + Point(1, 1)) {
+            self.canvas.draw_rect(rect)?;
+        }
+
+        Ok(())
+    }
+
+    /// Fills the cells from `from` up to `to`, excluded
+    fn draw_area(&mut self, from: Point, to: Point) -> Result<(), String> {
+        if to.0 <= from.0 || to.1 <= from.1 {
+            return Ok(());
+        }
+
+        for rect in self.screen_rects(from, to) {
+            self.canvas.fill_rect(rect)?;
+        }
+
+        Ok(())
+    }
+
+    /// Follows our snake, or the spectated one
+    fn update_camera(&mut self, context: &GameContext) {
+        let now = Instant::now();
+        let dt = now.duration_since(self.last_frame).as_secs_f32();
+        self.last_frame = now;
+
+        let target = context
+            .focus()
+            .and_then(|id| context.snakes.get(&id))
+            .map(|snake| (snake.head.0 as f32 + 0.5, snake.head.1 as f32 + 0.5));
+
+        let map = &context.map;
+        self.camera
+            .update(target, map, self.screen, self.dpi_scale, dt);
+
+        // Wrapping axes larger than the screen are drawn around the seam too
+        let view = self.camera.view_size(self.screen);
+        let repeat = |size: i32, view: f32| {
+            if map.edges == EdgeMode::Wrap && view < size as f32 {
+                size
+            } else {
+                0
+            }
+        };
+        self.repeat = (repeat(map.width, view.0), repeat(map.height, view.1));
+    }
+
+    /// Overview of the whole arena in the top right corner, when it doesn't fit on screen
+    fn draw_minimap(&mut self, context: &GameContext) -> Result<(), String> {
+        let map = &context.map;
+
+        if !self.show_minimap || self.camera.sees_all(map, self.screen) {
+            return Ok(());
+        }
+
+        let margin = (MINIMAP_MARGIN * self.dpi_scale) as i32;
+        let scale = MINIMAP_SIZE * self.dpi_scale / map.width.max(map.height) as f32;
+        let (width, height) = (
+            (map.width as f32 * scale) as u32,
+            (map.height as f32 * scale) as u32,
+        );
+        let area = Rect::new(
+            self.screen.0 as i32 - width as i32 - margin,
+            margin,
+            width,
+            height,
+        );
+
+        let cell_rect = |x: f32, y: f32, width: f32, height: f32| {
+            Rect::new(
+                area.x() + (x * scale) as i32,
+                area.y() + (y * scale) as i32,
+                (width * scale).ceil() as u32,
+                (height * scale).ceil() as u32,
+            )
+        };
+        let point_rect = |point: &Point| cell_rect(point.0 as f32, point.1 as f32, 1.0, 1.0);
+
+        self.canvas.set_draw_color(MINIMAP_BG_COLOR);
+        self.canvas.fill_rect(area)?;
+
+        self.canvas.set_clip_rect(area);
+
+        self.canvas.set_draw_color(WALL_COLOR);
+        for wall in map.walls.iter() {
+            self.canvas.fill_rect(point_rect(wall))?;
+        }
+
+        for (id, snake) in context.snakes.iter() {
+            let (body_color, head_color) = snake_colors(snake.team, Some(*id) == context.focus());
+
+            self.canvas.set_draw_color(body_color);
+            for point in snake.body.iter() {
+                self.canvas.fill_rect(point_rect(point))?;
+            }
+
+            self.canvas.set_draw_color(head_color);
+            self.canvas.fill_rect(point_rect(&snake.head))?;
+        }
+
+        // Visible part of the arena, around the seams if it wraps
+        let (center, view) = (self.camera.center, self.camera.view_size(self.screen));
+
+        self.canvas.set_draw_color(MINIMAP_VIEW_COLOR);
+        for dx in [-map.width, 0, map.width] {
+            for dy in [-map.height, 0, map.height] {
+                self.canvas.draw_rect(cell_rect(
+                    center.0 - view.0 / 2.0 + dx as f32,
+                    center.1 - view.1 / 2.0 + dy as f32,
+                    view.0,
+                    view.1,
+                ))?;
+            }
+        }
+
+        self.canvas.set_clip_rect(None);
+
+        self.canvas.set_draw_color(MINIMAP_BORDER_COLOR);
+        self.canvas.draw_rect(area)
+    }
+
+    /// Size of a font pixel for a text scale, in pixels
+    fn text_scale(&self, scale: f32) -> i32 {
+        ((scale * self.dpi_scale).round() as i32).max(1)
+    }
+
+    /// Width of a line of text, in pixels
+    fn text_width(text: &str, scale: i32) -> i32 {
+        let count = text.chars().count() as i32;
+
+        (count * (GLYPH_WIDTH + GLYPH_SPACING) - GLYPH_SPACING).max(0) * scale
+    }
+
+    /// Draws a line of text with the embedded font, `scale` being the size of a font pixel
+    pub fn draw_text(
+        &mut self,
+        text: &str,
+        x: i32,
+        y: i32,
+        scale: i32,
+        color: Color,
+    ) -> Result<(), String> {
+        let mut rects = Vec::new();
+
+        for (i, c) in text.chars().enumerate() {
+            let left = x + i as i32 * (GLYPH_WIDTH + GLYPH_SPACING) * scale;
+
+            for (row, bits) in glyph(c).iter().enumerate() {
+                for column in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0 {
+                        rects.push(Rect::new(
+                            left + column * scale,
+                            y + row as i32 * scale,
+                            scale as u32,
+                            scale as u32,
+                        ));
+                    }
+                }
+            }
+        }
+
+        // A drop shadow keeps the text readable over the arena, fading with the text
+        let mut shadow_color = TEXT_SHADOW_COLOR;
+        shadow_color.a = (shadow_color.a as u32 * color.a as u32 / 255) as u8;
+
+        let shadow: Vec<Rect> = rects
+            .iter()
+            .map(|rect| {
+                let mut rect = *rect;
+                rect.offset((scale / 2).max(1), (scale / 2).max(1));
+                rect
+            })
+            .collect();
+
+        self.canvas.set_draw_color(shadow_color);
+        self.canvas.fill_rects(&shadow)?;
+
+        self.canvas.set_draw_color(color);
+        self.canvas.fill_rects(&rects)
+    }
+
+    /// Score, length, ping and player count in the top left corner
+    fn draw_hud(&mut self, context: &GameContext) -> Result<(), String> {
+        let mut lines = Vec::new();
+
+        if context.is_spectator() && context.state == State::Playing {
+            lines.push((
+                match context.focus() {
+                    Some(id) => format!("Spectating snake {id}"),
+                    None => "Spectating the arena".to_string(),
+                },
+                TEXT_COLOR,
+            ));
+        }
+
+        if let Some(snake) = context.focus().and_then(|id| context.snakes.get(&id)) {
+            lines.push((format!("Score: {}", snake.score), TEXT_COLOR));
+            lines.push((format!("Length: {}", snake.body.len() + 1), TEXT_COLOR));
+        }
+
+        lines.push((format!("Players: {}", context.snakes.len()), TEXT_COLOR));
+        lines.push((
+            match context.ping {
+                Some(ping) => format!("Ping: {} ms", ping.as_millis()),
+                None => "Ping: -".to_string(),
+            },
+            TEXT_COLOR,
+        ));
+
+        for (i, score) in context.team_scores.iter().enumerate() {
+            let color = team_colors(i as u8 + 1).map_or(TEXT_COLOR, |(_, head)| *head);
+
+            lines.push((format!("Team {}: {score}", i + 1), color));
+        }
+
+        let scale = self.text_scale(HUD_TEXT_SCALE);
+        let margin = (HUD_MARGIN * self.dpi_scale) as i32;
+        let line_height = (GLYPH_HEIGHT + 3) * scale;
+
+        for (i, (line, color)) in lines.iter().enumerate() {
+            self.draw_text(line, margin, margin + i as i32 * line_height, scale, *color)?;
+        }
+
+        Ok(())
+    }
+
+    /// Last chat messages in the bottom left corner, above the chat box when it is open
+    fn draw_chat(&mut self, context: &GameContext) -> Result<(), String> {
+        let scale = self.text_scale(HUD_TEXT_SCALE);
+        let margin = (HUD_MARGIN * self.dpi_scale) as i32;
+        let line_height = (GLYPH_HEIGHT + 3) * scale;
+        let mut y = self.screen.1 as i32 - margin - GLYPH_HEIGHT * scale;
+
+        if let Some(input) = &context.chat_input {
+            let cursor_visible =
+                (self.start.elapsed().as_millis() / CURSOR_BLINK_PERIOD).is_multiple_of(2);
+            let text = format!("> {input}{}", if cursor_visible { "_" } else { "" });
+
+            self.draw_text(&text, margin, y, scale, SELECTED_TEXT_COLOR)?;
+            y -= line_height;
+        }
+
+        for line in context.chat.iter().rev().take(CHAT_LINES) {
+            let age = line.received.elapsed();
+
+            let alpha = if context.chat_input.is_some() || age < CHAT_VISIBLE_TIME {
+                1.0
+            } else {
+                1.0 - (age - CHAT_VISIBLE_TIME).as_secs_f32() / CHAT_FADE_TIME.as_secs_f32()
+            };
+
+            // Older messages are gone too
+            if alpha <= 0.0 {
+                break;
+            }
+
+            let mut sender_color = if line.snake_id == 0 {
+                SERVER_CHAT_COLOR
+            } else {
+                context
+                    .snakes
+                    .get(&line.snake_id)
+                    .map_or(TEXT_COLOR, |snake| snake_colors(snake.team, false).1)
+            };
+            let mut message_color = TEXT_COLOR;
+
+            sender_color.a = (alpha * 255.0) as u8;
+            message_color.a = (alpha * 255.0) as u8;
 
+            let sender = format!("{}: ", line.sender);
+
+            self.draw_text(&sender, margin, y, scale, sender_color)?;
+            self.draw_text(
+                &line.message,
+                margin + Self::text_width(&sender, scale) + GLYPH_SPACING * scale,
+                y,
+                scale,
+                message_color,
+            )?;
+
+            y -= line_height;
+        }
+
+        Ok(())
+    }
+
+    /// Connection status in the middle of the screen, over the dimmed game
+    fn draw_status(&mut self, context: &GameContext) -> Result<(), String> {
+        let (title, reason, color) = match &context.state {
+            State::Playing => return Ok(()),
+            State::Joining => ("Connecting...", None, TEXT_COLOR),
+            State::Rejected(reason) => ("Connection rejected", Some(reason), ERROR_COLOR),
+            State::Disconnected(reason) => ("Disconnected", Some(reason), ERROR_COLOR),
+            State::Reconnecting(reason) => ("Reconnecting...", Some(reason), TEXT_COLOR),
+            State::ServerClosed(reason) => ("Server closed", Some(reason), ERROR_COLOR),
+        };
+
+        self.canvas.set_draw_color(OVERLAY_COLOR);
         self.canvas
-            .fill_rect(Rect::new(x * SCALE as i32, y * SCALE as i32, SCALE, SCALE))?;
+            .fill_rect(Rect::new(0, 0, self.screen.0, self.screen.1))?;
+
+        let title_scale = self.text_scale(TITLE_TEXT_SCALE);
+        let scale = self.text_scale(HUD_TEXT_SCALE);
+        let center = (self.screen.0 as i32 / 2, self.screen.1 as i32 / 2);
+
+        self.draw_text(
+            title,
+            center.0 - Self::text_width(title, title_scale) / 2,
+            center.1 - (GLYPH_HEIGHT + 4) * title_scale,
+            title_scale,
+            color,
+        )?;
+
+        if let Some(reason) = reason {
+            let hint = "Press Escape to return to the menu";
+
+            for (i, line) in [reason.as_str(), hint].iter().enumerate() {
+                self.draw_text(
+                    line,
+                    center.0 - Self::text_width(line, scale) / 2,
+                    center.1 + i as i32 * (GLYPH_HEIGHT + 6) * scale,
+                    scale,
+                    TEXT_COLOR,
+                )?;
+            }
+        }
+
         Ok(())
     }
 
-    pub fn render(&mut self, context: &GameContext) -> Result<(), String> {
-        // Background
+    pub fn render_menu(&mut self, menu: &Menu, settings: &Settings) -> Result<(), String> {
         self.canvas.set_draw_color(BG_COLOR);
         self.canvas.clear();
 
+        let title_scale = self.text_scale(TITLE_TEXT_SCALE);
+        let scale = self.text_scale(HUD_TEXT_SCALE);
+        let line_height = (GLYPH_HEIGHT + 6) * scale;
+        let center = self.screen.0 as i32 / 2;
+
+        let entries = menu.entries(settings);
+        let title_height = (GLYPH_HEIGHT + 8) * title_scale;
+        let top = (self.screen.1 as i32 - title_height - entries.len() as i32 * line_height) / 2;
+
+        self.draw_text(
+            menu.title(),
+            center - Self::text_width(menu.title(), title_scale) / 2,
+            top,
+            title_scale,
+            TITLE_COLOR,
+        )?;
+
+        let cursor_visible =
+            (self.start.elapsed().as_millis() / CURSOR_BLINK_PERIOD).is_multiple_of(2);
+
+        for (i, entry) in entries.iter().enumerate() {
+            let (text, color) = if entry.selected {
+                let cursor = if entry.editing && cursor_visible {
+                    "_"
+                } else {
+                    " "
+                };
+
+                (format!("> {}{cursor}", entry.text), SELECTED_TEXT_COLOR)
+            } else {
+                (format!("  {} ", entry.text), TEXT_COLOR)
+            };
+
+            self.draw_text(
+                &text,
+                center - Self::text_width(&text, scale) / 2,
+                top + title_height + i as i32 * line_height,
+                scale,
+                color,
+            )?;
+        }
+
+        self.canvas.present();
+
+        Ok(())
+    }
+
+    pub fn render(&mut self, context: &GameContext) -> Result<(), String> {
+        self.update_camera(context);
+
+        // Background, letterboxed if the arena doesn't fill the window
+        self.canvas.set_draw_color(LETTERBOX_COLOR);
+        self.canvas.clear();
+
+        self.canvas.set_draw_color(BG_COLOR);
+        self.draw_area(Point(0, 0), Point(context.map.width, context.map.height))?;
+
+        // Lethal border
+        if context.map.edges == EdgeMode::Kill {
+            self.canvas.set_draw_color(BORDER_COLOR);
+            for rect in self.screen_rects(Point(0, 0), Point(context.map.width, context.map.height))
+            {
+                self.canvas.draw_rect(rect)?;
+            }
+        }
+
+        // Walls
+        self.canvas.set_draw_color(WALL_COLOR);
+        for wall in context.map.walls.iter() {
+            self.draw_point(wall)?;
+        }
+
+        // Capture the flag bases
+        for flag in context.flags.iter() {
+            let Some((color, _)) = team_colors(flag.team) else {
+                continue;
+            };
+
+            self.canvas
+                .set_draw_color(Color::RGBA(color.r, color.g, color.b, BASE_ALPHA));
+            self.draw_area(flag.base + Point(-1, -1), flag.base + Point(2, 2))?;
+        }
+
         // Snake(s)
         for (id, snake) in context.snakes.iter() {
-            if *id == context.snake_id {
-                self.canvas.set_draw_color(SNAKE_COLOR);
-            } else {
-                self.canvas.set_draw_color(ENEMY_BODY_COLOR);
+            let (mut body_color, mut head_color) =
+                snake_colors(snake.team, Some(*id) == context.focus());
+
+            // Effects about to end blink
+            let now = Instant::now();
+            let effect_visible = |power_up: PowerUp| match snake.effects.get(&power_up) {
+                Some(end) => {
+                    let left = end.saturating_duration_since(now);
+                    left > EFFECT_BLINK_TIME
+                        || (left.as_millis() / EFFECT_BLINK_PERIOD).is_multiple_of(2)
+                }
+                None => false,
+            };
+
+            if effect_visible(PowerUp::Invincibility) {
+                body_color = INVINCIBLE_COLOR;
             }
 
+            if effect_visible(PowerUp::Ghost) {
+                body_color.a = GHOST_ALPHA;
+                head_color.a = GHOST_ALPHA;
+            }
+
+            self.canvas.set_draw_color(body_color);
             for point in snake.body.iter() {
                 self.draw_point(point)?;
             }
 
-            if *id == context.snake_id {
-                self.canvas.set_draw_color(SNAKE_HEAD_COLOR);
-            } else {
-                self.canvas.set_draw_color(ENEMY_HEAD_COLOR);
-            }
+            self.canvas.set_draw_color(head_color);
             self.draw_point(&snake.head)?;
+
+            if effect_visible(PowerUp::Magnet) {
+                self.canvas.set_draw_color(MAGNET_COLOR);
+                self.draw_area(
+                    snake.head + Point(-MAGNET_RADIUS, -MAGNET_RADIUS),
+                    snake.head + Point(MAGNET_RADIUS + 1, MAGNET_RADIUS + 1),
+                )?;
+            }
+
+            if effect_visible(PowerUp::Shield) {
+                self.canvas.set_draw_color(SHIELD_COLOR);
+                self.outline_point(&snake.head)?;
+            }
+        }
+
+        // Power-ups
+        for (point, power_up) in context.power_ups.iter() {
+            self.canvas.set_draw_color(power_up_color(*power_up));
+            self.draw_point(point)?;
+
+            self.canvas.set_draw_color(BG_COLOR);
+            self.outline_point(point)?;
+        }
+
+        // Next cell of our snake, across the edges if they wrap, red if it dies there
+        if let Some((head, lethal)) = context.predicted_head() {
+            self.canvas.set_draw_color(if lethal {
+                BORDER_COLOR
+            } else {
+                PREDICTION_COLOR
+            });
+            self.outline_point(&head)?;
         }
 
         // Food
         self.canvas.set_draw_color(FOOD_COLOR);
-        self.draw_point(&context.food)?;
+        for food in context.food.iter() {
+            self.draw_point(food)?;
+        }
+
+        // Flags, lying around or on the head of their carrier
+        for flag in context.flags.iter() {
+            let Some((_, color)) = team_colors(flag.team) else {
+                continue;
+            };
+
+            let position = match context.snakes.get(&flag.carrier) {
+                Some(carrier) => carrier.head,
+                None => flag.position,
+            };
+
+            self.canvas.set_draw_color(*color);
+            self.draw_point(&position)?;
+
+            self.canvas.set_draw_color(FLAG_OUTLINE_COLOR);
+            self.outline_point(&position)?;
+        }
+
+        // Closed zone of a shrinking arena
+        let Bounds { min, max } = context.bounds;
+        let (width, height) = (context.map.width, context.map.height);
+
+        self.canvas.set_draw_color(CLOSED_ZONE_COLOR);
+        self.draw_area(Point(0, 0), Point(width, min.1))?;
+        self.draw_area(Point(0, max.1 + 1), Point(width, height))?;
+        self.draw_area(Point(0, min.1), Point(min.0, max.1 + 1))?;
+        self.draw_area(Point(max.0 + 1, min.1), Point(width, max.1 + 1))?;
+
+        self.draw_minimap(context)?;
+
+        if self.show_hud {
+            self.draw_hud(context)?;
+        }
+
+        self.draw_chat(context)?;
+
+        self.draw_status(context)?;
 
         self.canvas.present();
 
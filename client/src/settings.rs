@@ -0,0 +1,92 @@
+use std::{env, fs, path::PathBuf};
+
+/// How many recent servers are remembered
+const MAX_RECENT_SERVERS: usize = 5;
+const SETTINGS_FILE: &str = ".snake-multiplayer";
+
+/// Client preferences, saved in the home directory.
+///
+/// The file is plain text made of `key = value` lines, `server` lines listing
+/// the recent servers, the most recent first.
+pub struct Settings {
+    pub nickname: String,
+    pub fullscreen: bool,
+    pub minimap: bool,
+    pub hud: bool,
+    pub recent_servers: Vec<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            nickname: String::new(),
+            fullscreen: false,
+            minimap: true,
+            hud: true,
+            recent_servers: Vec::new(),
+        }
+    }
+}
+
+impl Settings {
+    fn path() -> PathBuf {
+        let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"));
+
+        home.map(PathBuf::from)
+            .unwrap_or_default()
+            .join(SETTINGS_FILE)
+    }
+
+    /// Loads the saved settings, the defaults if there are none
+    pub fn load() -> Self {
+        let mut settings = Settings::default();
+
+        let Ok(text) = fs::read_to_string(Self::path()) else {
+            return settings;
+        };
+
+        for line in text.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+
+            match key.trim() {
+                "nickname" => settings.nickname = value.to_string(),
+                "fullscreen" => settings.fullscreen = value == "true",
+                "minimap" => settings.minimap = value == "true",
+                "hud" => settings.hud = value == "true",
+                "server" => {
+                    if settings.recent_servers.len() < MAX_RECENT_SERVERS {
+                        settings.recent_servers.push(value.to_string());
+                    }
+                }
+                key => eprintln!("WARN: Unknown setting {key}"),
+            }
+        }
+
+        settings
+    }
+
+    pub fn save(&self) {
+        let mut text = format!(
+            "nickname = {}\nfullscreen = {}\nminimap = {}\nhud = {}\n",
+            self.nickname, self.fullscreen, self.minimap, self.hud
+        );
+
+        for server in self.recent_servers.iter() {
+            text.push_str(&format!("server = {server}\n"));
+        }
+
+        if let Err(err) = fs::write(Self::path(), text) {
+            eprintln!("WARN: Could not save the settings: {err}");
+        }
+    }
+
+    /// Moves a server to the top of the recent servers
+    pub fn add_recent_server(&mut self, address: &str) {
+        self.recent_servers.retain(|server| server != address);
+        self.recent_servers.insert(0, address.to_string());
+        self.recent_servers.truncate(MAX_RECENT_SERVERS);
+    }
+}
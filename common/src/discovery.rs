@@ -0,0 +1,108 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use crate::{
+    mode::GameMode,
+    packet::{PacketBuilder, PacketType, ReadablePacket},
+};
+
+/// UDP port servers listen to for discovery probes
+pub const DISCOVERY_PORT: u16 = 14301;
+
+/// What a server tells about itself to the clients looking for a game
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerInfo {
+    pub name: String,
+    pub players: u8,
+    pub max_players: u8,
+    pub mode: GameMode,
+    /// TCP port to play on
+    pub port: u16,
+}
+
+impl ServerInfo {
+    pub fn to_packet(&self) -> Vec<u8> {
+        let mut packet =
+            PacketBuilder::with_capacity(PacketType::DiscoveryResponse, self.name.len() + 6);
+
+        packet.write_str(&self.name);
+        packet.write(self.players);
+        packet.write(self.max_players);
+        packet.write(self.mode.to_u8());
+        packet.write_u16_le(self.port);
+
+        packet.build()
+    }
+
+    /// Reads a Discovery response, none if it is malformed
+    pub fn from_packet(packet: &mut ReadablePacket) -> Option<Self> {
+        if packet.r#type != PacketType::DiscoveryResponse {
+            return None;
+        }
+
+        // The packet comes from whoever answered, check its size before reading it
+        let name_len = *packet.buffer.first()? as usize;
+
+        if packet.remaining() != 1 + name_len + 5 {
+            return None;
+        }
+
+        Some(Self {
+            name: packet.read_str(),
+            players: packet.read(),
+            max_players: packet.read(),
+            mode: GameMode::from_u8(packet.read())?,
+            port: packet.read_u16_le(),
+        })
+    }
+}
+
+/// Sent by clients, broadcast on the LAN, to find the servers
+pub fn probe_packet() -> Vec<u8> {
+    PacketBuilder::new(PacketType::DiscoveryRequest).build()
+}
+
+/// A server that answered a discovery probe
+pub struct DiscoveredServer {
+    /// Address to connect to, `ip:port`
+    pub address: String,
+    pub info: ServerInfo,
+}
+
+/// The servers that answered the probes of a client, each listed once
+pub struct ServerList {
+    /// Address of this machine on the LAN, if known
+    local_ip: Option<IpAddr>,
+    servers: Vec<DiscoveredServer>,
+}
+
+impl ServerList {
+    pub fn new(local_ip: Option<IpAddr>) -> Self {
+        Self {
+            local_ip,
+            servers: Vec::new(),
+        }
+    }
+
+    /// Lists the server behind an answer, unless it already is. The servers running on
+    /// this machine answer both the broadcast and the loopback probes, they are listed
+    /// at their loopback address.
+    pub fn add(&mut self, from: IpAddr, info: ServerInfo) {
+        let ip = match self.local_ip {
+            Some(local_ip) if local_ip == from => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            _ => from,
+        };
+        let address = SocketAddr::new(ip, info.port).to_string();
+
+        if !self.servers.iter().any(|server| server.address == address) {
+            self.servers.push(DiscoveredServer { address, info });
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.servers.clear();
+    }
+
+    pub fn servers(&self) -> &[DiscoveredServer] {
+        &self.servers
+    }
+}
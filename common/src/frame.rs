@@ -0,0 +1,101 @@
+use miniz_oxide::{deflate::compress_to_vec, inflate::decompress_to_vec_with_limit};
+
+/// Set in the type byte of a frame whose payload is compressed
+pub const COMPRESSED: u8 = 0x80;
+/// Type byte of the frames carrying a piece of a packet too large for a single frame
+pub const FRAGMENT: u8 = 0x1f;
+/// Smaller payloads are not worth compressing
+pub const COMPRESSION_THRESHOLD: usize = 256;
+/// Longest frame, type byte included, as its length is a u16
+pub const MAX_FRAME_LEN: usize = u16::MAX as usize;
+/// Longest packet once decompressed or reassembled, larger ones are dropped
+pub const MAX_PACKET_LEN: usize = 1 << 20;
+
+const COMPRESSION_LEVEL: u8 = 6;
+
+fn frame(body: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(body.len() + 2);
+    frame.extend_from_slice(&(body.len() as u16).to_le_bytes());
+    frame.extend_from_slice(body);
+
+    frame
+}
+
+/// Frames a packet for a client reading compressed and fragmented frames. The payload
+/// is compressed when it gets smaller, then split into fragments if it still does not fit.
+pub fn encode(packet_type: u8, payload: &[u8]) -> Vec<u8> {
+    let mut body = vec![packet_type];
+
+    match payload.len() >= COMPRESSION_THRESHOLD {
+        true => {
+            let compressed = compress_to_vec(payload, COMPRESSION_LEVEL);
+
+            if compressed.len() < payload.len() {
+                body[0] |= COMPRESSED;
+                body.extend_from_slice(&compressed);
+            } else {
+                body.extend_from_slice(payload);
+            }
+        }
+        false => body.extend_from_slice(payload),
+    }
+
+    if body.len() <= MAX_FRAME_LEN {
+        return frame(&body);
+    }
+
+    // Each fragment starts with its type and whether more follow
+    let chunks = body.chunks(MAX_FRAME_LEN - 2);
+    let last = chunks.len() - 1;
+    let mut frames = Vec::with_capacity(body.len() + chunks.len() * 4);
+
+    for (i, chunk) in chunks.enumerate() {
+        frames.extend_from_slice(&((chunk.len() + 2) as u16).to_le_bytes());
+        frames.push(FRAGMENT);
+        frames.push((i < last) as u8);
+        frames.extend_from_slice(chunk);
+    }
+
+    frames
+}
+
+/// Inflates the payload of a compressed frame, none if it is invalid or too large
+pub fn decompress(payload: &[u8]) -> Option<Vec<u8>> {
+    decompress_to_vec_with_limit(payload, MAX_PACKET_LEN).ok()
+}
+
+/// Puts the packets split into fragments back together
+#[derive(Default)]
+pub struct Reassembler {
+    pending: Vec<u8>,
+    /// Whether the packet being received went over the size limit, and is dropped
+    overflowed: bool,
+}
+
+impl Reassembler {
+    /// Takes the frames in the order they were received, without their length.
+    /// Returns the frames to read as packets, none while waiting for more fragments.
+    pub fn push(&mut self, frame: Vec<u8>) -> Option<Vec<u8>> {
+        let [FRAGMENT, more, chunk @ ..] = &frame[..] else {
+            return Some(frame);
+        };
+
+        if self.pending.len() + chunk.len() > MAX_PACKET_LEN {
+            self.pending.clear();
+            self.overflowed = true;
+        }
+
+        if !self.overflowed {
+            self.pending.extend_from_slice(chunk);
+        }
+
+        if *more != 0 {
+            return None;
+        }
+
+        match std::mem::take(&mut self.overflowed) {
+            true => None,
+            false => Some(std::mem::take(&mut self.pending)),
+        }
+    }
+}
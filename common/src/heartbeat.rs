@@ -0,0 +1,49 @@
+use std::time::{Duration, Instant};
+
+use crate::packet::{PacketBuilder, PacketType, ReadablePacket};
+
+/// How often both sides send a Ping
+pub const PING_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Timestamps carried by pings, in milliseconds since the clock started, wrapping around
+#[derive(Clone, Copy)]
+pub struct Clock {
+    started: Instant,
+}
+
+impl Clock {
+    pub fn start() -> Self {
+        Self {
+            started: Instant::now(),
+        }
+    }
+
+    pub fn timestamp(&self) -> u32 {
+        self.started.elapsed().as_millis() as u32
+    }
+
+    /// Time elapsed since this clock gave a timestamp
+    pub fn since(&self, timestamp: u32) -> Duration {
+        Duration::from_millis(self.timestamp().wrapping_sub(timestamp) as u64)
+    }
+}
+
+pub fn ping_packet(timestamp: u32) -> Vec<u8> {
+    let mut packet = PacketBuilder::with_capacity(PacketType::Ping, 4);
+    packet.write_u32_le(timestamp);
+
+    packet.build()
+}
+
+/// The answer to a Ping, echoing its timestamp
+pub fn pong_packet(timestamp: u32) -> Vec<u8> {
+    let mut packet = PacketBuilder::with_capacity(PacketType::Pong, 4);
+    packet.write_u32_le(timestamp);
+
+    packet.build()
+}
+
+/// Reads the timestamp of a Ping or a Pong, none if it is malformed
+pub fn read_timestamp(packet: &mut ReadablePacket) -> Option<u32> {
+    packet.try_read_u32_le()
+}
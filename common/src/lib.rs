@@ -1,2 +1,11 @@
+pub mod discovery;
+pub mod frame;
+pub mod heartbeat;
+pub mod map;
+pub mod mode;
 pub mod packet;
+pub mod power_up;
+pub mod shutdown;
+pub mod snapshot;
+pub mod status;
 pub mod util;
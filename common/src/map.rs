@@ -0,0 +1,223 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    str::FromStr,
+};
+
+use crate::util::Point;
+
+pub const DEFAULT_WIDTH: i32 = 80;
+pub const DEFAULT_HEIGHT: i32 = 60;
+/// Walls that fit in a Map data packet, its frame length being a u16
+pub const MAX_WALLS: usize = (u16::MAX as usize - 6) / 2;
+
+/// What happens to a snake that goes past the arena edges
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EdgeMode {
+    Wrap,
+    Kill,
+}
+
+impl EdgeMode {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0x0 => Some(EdgeMode::Wrap),
+            0x1 => Some(EdgeMode::Kill),
+            _ => None,
+        }
+    }
+
+    pub fn to_u8(self) -> u8 {
+        match self {
+            EdgeMode::Wrap => 0x0,
+            EdgeMode::Kill => 0x1,
+        }
+    }
+}
+
+impl FromStr for EdgeMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wrap" => Ok(EdgeMode::Wrap),
+            "kill" => Ok(EdgeMode::Kill),
+            _ => Err(format!("Invalid edge mode {s}, expected wrap or kill")),
+        }
+    }
+}
+
+/// The playable rectangle of the arena, both corners included
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub min: Point,
+    pub max: Point,
+}
+
+impl Bounds {
+    pub fn contains(&self, point: &Point) -> bool {
+        (self.min.0..=self.max.0).contains(&point.0) && (self.min.1..=self.max.1).contains(&point.1)
+    }
+
+    /// Closes the outer ring of cells, returns false if the bounds are already too small
+    pub fn shrink(&mut self, min_size: i32) -> bool {
+        if self.max.0 - self.min.0 + 1 - 2 < min_size || self.max.1 - self.min.1 + 1 - 2 < min_size
+        {
+            return false;
+        }
+
+        self.min = self.min + Point(1, 1);
+        self.max = self.max + Point(-1, -1);
+
+        true
+    }
+}
+
+/// The arena layout.
+///
+/// Map files are plain text: `key = value` option lines (`edges = wrap|kill`)
+/// followed by the grid, where `#` is a wall, `S` a spawn cell, `1` to `4` a team base
+/// and `.` an empty cell.
+/// Empty lines and lines starting with `//` are ignored.
+pub struct Map {
+    pub width: i32,
+    pub height: i32,
+    pub edges: EdgeMode,
+    pub walls: HashSet<Point>,
+    /// Cells where snakes may spawn, anywhere free if empty
+    pub spawns: Vec<Point>,
+    /// Base of each team, by team
+    pub bases: HashMap<u8, Point>,
+}
+
+impl Default for Map {
+    fn default() -> Self {
+        Self {
+            width: DEFAULT_WIDTH,
+            height: DEFAULT_HEIGHT,
+            edges: EdgeMode::Wrap,
+            walls: HashSet::new(),
+            spawns: Vec::new(),
+            bases: HashMap::new(),
+        }
+    }
+}
+
+impl Map {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text =
+            fs::read_to_string(path).map_err(|err| format!("Could not read map {path}: {err}"))?;
+
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut map = Map {
+            width: 0,
+            height: 0,
+            ..Default::default()
+        };
+
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+
+            if let Some((key, value)) = line.split_once('=') {
+                match key.trim() {
+                    "edges" => map.edges = value.trim().parse()?,
+                    key => return Err(format!("Unknown map option {key}")),
+                }
+
+                continue;
+            }
+
+            let y = map.height;
+            let row_width = line.chars().count() as i32;
+
+            if map.width == 0 {
+                map.width = row_width;
+            } else if row_width != map.width {
+                return Err(format!(
+                    "Map row {y} has {row_width} cells, expected {}",
+                    map.width
+                ));
+            }
+
+            for (x, cell) in line.chars().enumerate() {
+                let point = Point(x as i32, y);
+
+                match cell {
+                    '#' => {
+                        map.walls.insert(point);
+                    }
+                    'S' => map.spawns.push(point),
+                    '1'..='4' => {
+                        map.bases.insert(cell as u8 - b'0', point);
+                    }
+                    '.' => {}
+                    _ => return Err(format!("Invalid map cell '{cell}' at ({x}, {y})")),
+                }
+            }
+
+            map.height += 1;
+        }
+
+        if map.width == 0 || map.height == 0 {
+            return Err("Map has no cells".to_string());
+        }
+
+        // Coordinates are sent as a single byte
+        if map.width > 255 || map.height > 255 {
+            return Err(format!(
+                "Map is too big ({}x{}), the maximum is 255x255",
+                map.width, map.height
+            ));
+        }
+
+        if map.walls.len() > MAX_WALLS {
+            return Err(format!(
+                "Map has too many walls ({}), the maximum is {MAX_WALLS}",
+                map.walls.len()
+            ));
+        }
+
+        Ok(map)
+    }
+
+    pub fn contains(&self, point: &Point) -> bool {
+        (0..self.width).contains(&point.0) && (0..self.height).contains(&point.1)
+    }
+
+    /// The whole map
+    pub fn bounds(&self) -> Bounds {
+        Bounds {
+            min: Point(0, 0),
+            max: Point(self.width - 1, self.height - 1),
+        }
+    }
+
+    /// Every cell of the map, row by row
+    pub fn cells(&self) -> impl Iterator<Item = Point> {
+        let width = self.width;
+
+        (0..self.height).flat_map(move |y| (0..width).map(move |x| Point(x, y)))
+    }
+
+    pub fn is_wall(&self, point: &Point) -> bool {
+        self.walls.contains(point)
+    }
+
+    /// Brings a point that went past the edges back into the arena,
+    /// only if the edges wrap
+    pub fn wrap(&self, point: Point) -> Point {
+        if self.edges == EdgeMode::Kill {
+            return point;
+        }
+
+        Point(
+            point.0.rem_euclid(self.width),
+            point.1.rem_euclid(self.height),
+        )
+    }
+}
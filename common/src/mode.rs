@@ -0,0 +1,59 @@
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum GameMode {
+    #[default]
+    Classic,
+    /// The arena shrinks over time, killing the snakes caught outside
+    BattleRoyale,
+    /// Snakes are split into teams, scoring together
+    Teams,
+    /// Two teams steal each other's flag and bring it back to their base
+    CaptureTheFlag,
+}
+
+impl GameMode {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0x0 => Some(GameMode::Classic),
+            0x1 => Some(GameMode::BattleRoyale),
+            0x2 => Some(GameMode::Teams),
+            0x3 => Some(GameMode::CaptureTheFlag),
+            _ => None,
+        }
+    }
+
+    pub fn to_u8(self) -> u8 {
+        match self {
+            GameMode::Classic => 0x0,
+            GameMode::BattleRoyale => 0x1,
+            GameMode::Teams => 0x2,
+            GameMode::CaptureTheFlag => 0x3,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            GameMode::Classic => "classic",
+            GameMode::BattleRoyale => "royale",
+            GameMode::Teams => "teams",
+            GameMode::CaptureTheFlag => "ctf",
+        }
+    }
+}
+
+impl FromStr for GameMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "classic" => Ok(GameMode::Classic),
+            "royale" => Ok(GameMode::BattleRoyale),
+            "teams" => Ok(GameMode::Teams),
+            "ctf" => Ok(GameMode::CaptureTheFlag),
+            _ => Err(format!(
+                "Invalid game mode {s}, expected classic, royale, teams or ctf"
+            )),
+        }
+    }
+}
@@ -1,12 +1,103 @@
+use crate::{
+    frame::{self, COMPRESSED},
+    util::Point,
+};
+
+/// Longest nickname sent in a Join packet, in characters
+pub const MAX_NICKNAME_LEN: usize = 16;
+/// Longest chat message, in characters
+pub const MAX_CHAT_LEN: usize = 120;
+
+/// Capability of clients that read the snake moves of snapshots packed as steps
+pub const CAP_PACKED_STEPS: u8 = 0x1;
+/// Capability of clients that read compressed and fragmented frames
+pub const CAP_COMPRESSION: u8 = 0x2;
+/// Capability of clients that read Snapshot packets, the server refuses the others
+pub const CAP_SNAPSHOTS: u8 = 0x4;
+
+/// A move to a neighbouring cell, packed in 2 bits
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Step {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Step {
+    /// The step from a cell to a neighbouring one, none if they are not neighbours
+    pub fn between(from: Point, to: Point) -> Option<Self> {
+        match (to.0 - from.0, to.1 - from.1) {
+            (0, -1) => Some(Self::Up),
+            (0, 1) => Some(Self::Down),
+            (-1, 0) => Some(Self::Left),
+            (1, 0) => Some(Self::Right),
+            _ => None,
+        }
+    }
+
+    /// The cell reached by taking this step
+    pub fn apply(self, point: Point) -> Point {
+        match self {
+            Self::Up => point + Point(0, -1),
+            Self::Down => point + Point(0, 1),
+            Self::Left => point + Point(-1, 0),
+            Self::Right => point + Point(1, 0),
+        }
+    }
+
+    fn to_bits(self) -> u8 {
+        match self {
+            Self::Up => 0,
+            Self::Down => 1,
+            Self::Left => 2,
+            Self::Right => 3,
+        }
+    }
+
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0 => Self::Up,
+            1 => Self::Down,
+            2 => Self::Left,
+            _ => Self::Right,
+        }
+    }
+}
+
+/// Types 0x4, 0x5 and 0xf belonged to the HeadUpdate, SnakeConnect and TailShed packets
+/// that Snapshot packets replaced. They stay reserved, so that outdated clients never
+/// mistake a newer packet for one of them.
 #[derive(PartialEq)]
 pub enum PacketType {
     Info,
     FoodUpdate,
     DirectionUpdate,
-    HeadUpdate,
-    SnakeConnect,
     SnakeDisconnect,
     ConnRejected,
+    FoodSpawn,
+    MapData,
+    ArenaBounds,
+    Join,
+    ScoreUpdate,
+    FlagUpdate,
+    Boost,
+    PowerUpUpdate,
+    EffectStart,
+    EffectEnd,
+    DiscoveryRequest,
+    DiscoveryResponse,
+    StatusRequest,
+    StatusResponse,
+    ChatSend,
+    ChatMessage,
+    Ping,
+    Pong,
+    Session,
+    ServerShutdown,
+    Snapshot,
+    SnapshotAck,
+    Fragment,
 }
 
 pub struct PacketBuilder {
@@ -37,17 +128,74 @@ impl PacketBuilder {
         self.buffer.extend_from_slice(&value.to_le_bytes());
     }
 
-    pub fn build(&self) -> Vec<u8> {
-        let packet_type = match self.r#type {
+    pub fn write_u32_le(&mut self, value: u32) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64_le(&mut self, value: u64) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Writes UTF-8 text prefixed with its length, cut to 255 bytes
+    pub fn write_str(&mut self, value: &str) {
+        let mut len = value.len().min(u8::MAX as usize);
+
+        while !value.is_char_boundary(len) {
+            len -= 1;
+        }
+
+        self.buffer.push(len as u8);
+        self.buffer.extend_from_slice(&value.as_bytes()[..len]);
+    }
+
+    /// Writes steps 4 to a byte, the first one in the lowest bits.
+    /// The reader has to know how many there are.
+    pub fn write_steps(&mut self, steps: &[Step]) {
+        for chunk in steps.chunks(4) {
+            let byte = chunk
+                .iter()
+                .enumerate()
+                .fold(0, |byte, (i, step)| byte | step.to_bits() << (i * 2));
+
+            self.buffer.push(byte);
+        }
+    }
+
+    fn type_byte(&self) -> u8 {
+        match self.r#type {
             PacketType::Info => 0x1,
             PacketType::FoodUpdate => 0x2,
             PacketType::DirectionUpdate => 0x3,
-            PacketType::HeadUpdate => 0x4,
-            PacketType::SnakeConnect => 0x5,
             PacketType::SnakeDisconnect => 0x6,
             PacketType::ConnRejected => 0x7,
-        };
+            PacketType::FoodSpawn => 0x8,
+            PacketType::MapData => 0x9,
+            PacketType::ArenaBounds => 0xa,
+            PacketType::Join => 0xb,
+            PacketType::ScoreUpdate => 0xc,
+            PacketType::FlagUpdate => 0xd,
+            PacketType::Boost => 0xe,
+            PacketType::PowerUpUpdate => 0x10,
+            PacketType::EffectStart => 0x11,
+            PacketType::EffectEnd => 0x12,
+            PacketType::DiscoveryRequest => 0x13,
+            PacketType::DiscoveryResponse => 0x14,
+            PacketType::StatusRequest => 0x15,
+            PacketType::StatusResponse => 0x16,
+            PacketType::ChatSend => 0x17,
+            PacketType::ChatMessage => 0x18,
+            PacketType::Ping => 0x19,
+            PacketType::Pong => 0x1a,
+            PacketType::Session => 0x1b,
+            PacketType::ServerShutdown => 0x1c,
+            PacketType::Snapshot => 0x1d,
+            PacketType::SnapshotAck => 0x1e,
+            PacketType::Fragment => frame::FRAGMENT,
+        }
+    }
 
+    pub fn build(&self) -> Vec<u8> {
+        let packet_type = self.type_byte();
         let packet_len = (self.buffer.len() + 1) as u16;
 
         let mut packet = Vec::with_capacity(self.buffer.len() + 3);
@@ -57,6 +205,15 @@ impl PacketBuilder {
 
         packet
     }
+
+    /// Like `build`, large packets being compressed and split into fragments
+    /// for the clients with the compression capability
+    pub fn build_for(&self, capabilities: u8) -> Vec<u8> {
+        match capabilities & CAP_COMPRESSION != 0 {
+            true => frame::encode(self.type_byte(), &self.buffer),
+            false => self.build(),
+        }
+    }
 }
 
 pub struct ReadablePacket {
@@ -67,22 +224,72 @@ pub struct ReadablePacket {
 
 impl ReadablePacket {
     pub fn from_bytes(bytes: &[u8]) -> Self {
-        let packet_type = match bytes[0] {
+        Self::try_from_bytes(bytes).expect("Unknown packet type")
+    }
+
+    /// Parses a packet that may not come from the game, none if it is empty, of an unknown type
+    /// or compressed. Only the server compresses frames, so the packets of clients never are.
+    pub fn try_from_bytes(bytes: &[u8]) -> Option<Self> {
+        let type_byte = *bytes.first()?;
+
+        if type_byte & COMPRESSED != 0 {
+            return None;
+        }
+
+        Self::parse(type_byte, bytes[1..].to_vec())
+    }
+
+    /// Like `try_from_bytes` for packets sent by the server, inflating compressed frames.
+    /// None if the payload is badly compressed or too large once inflated.
+    pub fn try_from_server_bytes(bytes: &[u8]) -> Option<Self> {
+        let type_byte = *bytes.first()?;
+
+        let buffer = match type_byte & COMPRESSED != 0 {
+            true => frame::decompress(&bytes[1..])?,
+            false => bytes[1..].to_vec(),
+        };
+
+        Self::parse(type_byte & !COMPRESSED, buffer)
+    }
+
+    fn parse(type_byte: u8, buffer: Vec<u8>) -> Option<Self> {
+        let packet_type = match type_byte {
             0x1 => PacketType::Info,
             0x2 => PacketType::FoodUpdate,
             0x3 => PacketType::DirectionUpdate,
-            0x4 => PacketType::HeadUpdate,
-            0x5 => PacketType::SnakeConnect,
             0x6 => PacketType::SnakeDisconnect,
             0x7 => PacketType::ConnRejected,
-            _ => panic!("Unknown packet type"),
+            0x8 => PacketType::FoodSpawn,
+            0x9 => PacketType::MapData,
+            0xa => PacketType::ArenaBounds,
+            0xb => PacketType::Join,
+            0xc => PacketType::ScoreUpdate,
+            0xd => PacketType::FlagUpdate,
+            0xe => PacketType::Boost,
+            0x10 => PacketType::PowerUpUpdate,
+            0x11 => PacketType::EffectStart,
+            0x12 => PacketType::EffectEnd,
+            0x13 => PacketType::DiscoveryRequest,
+            0x14 => PacketType::DiscoveryResponse,
+            0x15 => PacketType::StatusRequest,
+            0x16 => PacketType::StatusResponse,
+            0x17 => PacketType::ChatSend,
+            0x18 => PacketType::ChatMessage,
+            0x19 => PacketType::Ping,
+            0x1a => PacketType::Pong,
+            0x1b => PacketType::Session,
+            0x1c => PacketType::ServerShutdown,
+            0x1d => PacketType::Snapshot,
+            0x1e => PacketType::SnapshotAck,
+            frame::FRAGMENT => PacketType::Fragment,
+            _ => return None,
         };
 
-        Self {
+        Some(Self {
             r#type: packet_type,
-            buffer: bytes[1..].to_vec(),
+            buffer,
             cursor: 0,
-        }
+        })
     }
 
     pub fn read(&mut self) -> u8 {
@@ -100,6 +307,77 @@ impl ReadablePacket {
         u16::from_le_bytes(bytes)
     }
 
+    pub fn read_u32_le(&mut self) -> u32 {
+        let bytes = &self.buffer[self.cursor..self.cursor + 4];
+
+        self.cursor += 4;
+
+        u32::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    pub fn read_u64_le(&mut self) -> u64 {
+        let bytes = &self.buffer[self.cursor..self.cursor + 8];
+
+        self.cursor += 8;
+
+        u64::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    /// Reads text written by `PacketBuilder::write_str`, invalid UTF-8 is replaced
+    pub fn read_str(&mut self) -> String {
+        let len = self.read() as usize;
+        let bytes = &self.buffer[self.cursor..self.cursor + len];
+
+        self.cursor += len;
+
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+
+    /// Like `read`, none if the packet is too short
+    pub fn try_read(&mut self) -> Option<u8> {
+        (self.remaining() >= 1).then(|| self.read())
+    }
+
+    /// Like `read_u16_le`, none if the packet is too short
+    pub fn try_read_u16_le(&mut self) -> Option<u16> {
+        (self.remaining() >= 2).then(|| self.read_u16_le())
+    }
+
+    /// Like `read_u32_le`, none if the packet is too short
+    pub fn try_read_u32_le(&mut self) -> Option<u32> {
+        (self.remaining() >= 4).then(|| self.read_u32_le())
+    }
+
+    /// Like `read_u64_le`, none if the packet is too short
+    pub fn try_read_u64_le(&mut self) -> Option<u64> {
+        (self.remaining() >= 8).then(|| self.read_u64_le())
+    }
+
+    /// Like `read_str`, none if the packet is too short
+    pub fn try_read_str(&mut self) -> Option<String> {
+        let len = *self.buffer.get(self.cursor)? as usize;
+
+        (self.remaining() > len).then(|| self.read_str())
+    }
+
+    /// Reads `count` steps written by `PacketBuilder::write_steps`, none if the packet is too short
+    pub fn try_read_steps(&mut self, count: usize) -> Option<Vec<Step>> {
+        let len = count.div_ceil(4);
+
+        if self.remaining() < len {
+            return None;
+        }
+
+        let bytes = &self.buffer[self.cursor..self.cursor + len];
+        self.cursor += len;
+
+        Some(
+            (0..count)
+                .map(|i| Step::from_bits(bytes[i / 4] >> (i % 4 * 2)))
+                .collect(),
+        )
+    }
+
     pub fn remaining(&self) -> usize {
         self.buffer.len() - self.cursor
     }
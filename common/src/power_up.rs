@@ -0,0 +1,42 @@
+/// A magnet pulls in the food this close to the head
+pub const MAGNET_RADIUS: i32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PowerUp {
+    /// Bumping into snakes does not kill
+    Invincibility,
+    /// Goes through snakes, and snakes go through it
+    Ghost,
+    /// Pulls in the food around the head
+    Magnet,
+    /// Survives the next collision with a snake
+    Shield,
+}
+
+impl PowerUp {
+    pub const ALL: [PowerUp; 4] = [
+        PowerUp::Invincibility,
+        PowerUp::Ghost,
+        PowerUp::Magnet,
+        PowerUp::Shield,
+    ];
+
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0x1 => Some(PowerUp::Invincibility),
+            0x2 => Some(PowerUp::Ghost),
+            0x3 => Some(PowerUp::Magnet),
+            0x4 => Some(PowerUp::Shield),
+            _ => None,
+        }
+    }
+
+    pub fn to_u8(self) -> u8 {
+        match self {
+            PowerUp::Invincibility => 0x1,
+            PowerUp::Ghost => 0x2,
+            PowerUp::Magnet => 0x3,
+            PowerUp::Shield => 0x4,
+        }
+    }
+}
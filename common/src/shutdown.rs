@@ -0,0 +1,42 @@
+use std::time::Duration;
+
+use crate::packet::{PacketBuilder, PacketType, ReadablePacket};
+
+/// Sent to every client right before the server closes
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShutdownNotice {
+    pub reason: String,
+    /// When the server should be back, none if it was not announced
+    pub restart_in: Option<Duration>,
+}
+
+impl ShutdownNotice {
+    pub fn to_packet(&self) -> Vec<u8> {
+        let mut packet =
+            PacketBuilder::with_capacity(PacketType::ServerShutdown, 3 + self.reason.len());
+
+        packet.write_str(&self.reason);
+        // 0 when no restart was announced
+        packet.write_u16_le(
+            self.restart_in
+                .map_or(0, |eta| eta.as_secs().clamp(1, u16::MAX as u64) as u16),
+        );
+
+        packet.build()
+    }
+
+    /// Reads a Server shutdown packet, none if it is malformed
+    pub fn from_packet(packet: &mut ReadablePacket) -> Option<Self> {
+        if packet.r#type != PacketType::ServerShutdown {
+            return None;
+        }
+
+        let reason = packet.try_read_str()?;
+        let restart_in = packet.try_read_u16_le()?;
+
+        Some(Self {
+            reason,
+            restart_in: (restart_in > 0).then(|| Duration::from_secs(restart_in as u64)),
+        })
+    }
+}
@@ -0,0 +1,361 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::{
+    packet::{PacketBuilder, PacketType, ReadablePacket, Step},
+    util::Point,
+};
+
+/// Snapshots kept by both sides, deltas can only be made against these
+pub const SNAPSHOT_HISTORY: usize = 64;
+
+/// A snake as the clients see it
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnakeState {
+    /// Team of the snake, 0 if it plays alone
+    pub team: u8,
+    /// Every cell of the snake, from its tail to its head
+    pub cells: Vec<Point>,
+}
+
+/// The snakes at a given tick, numbered so that deltas can refer to it
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Snapshot {
+    /// Never 0, which stands for no snapshot at all
+    pub seq: u32,
+    pub snakes: BTreeMap<u8, SnakeState>,
+}
+
+/// How a snake changed since the base snapshot
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnakeChange {
+    Removed,
+    /// A new snake, or one that changed too much to be described by its moves
+    Spawned(SnakeState),
+    /// Cells dropped from the tail, then cells added at the head
+    Moved {
+        trimmed: u8,
+        cells: Vec<Point>,
+    },
+    /// Like `Moved`, the new cells being steps from the head
+    Stepped {
+        trimmed: u8,
+        steps: Vec<Step>,
+    },
+}
+
+/// A snapshot as sent to a client: the whole state, or what changed since a
+/// snapshot the client acknowledged
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotUpdate {
+    pub seq: u32,
+    /// Snapshot the changes apply to, none for a keyframe
+    pub base: Option<u32>,
+    /// Changed snakes only, the others are as in the base
+    pub changes: Vec<(u8, SnakeChange)>,
+}
+
+/// Describes the moves of a snake as steps from its head, none if it did not only move
+/// to neighbouring cells, as when it wrapped around the arena
+fn steps(old: &SnakeState, change: &SnakeChange) -> Option<SnakeChange> {
+    let SnakeChange::Moved { trimmed, cells } = change else {
+        return None;
+    };
+
+    let mut head = *old.cells.last()?;
+    let mut steps = Vec::with_capacity(cells.len());
+
+    for cell in cells.iter() {
+        steps.push(Step::between(head, *cell)?);
+        head = *cell;
+    }
+
+    Some(SnakeChange::Stepped {
+        trimmed: *trimmed,
+        steps,
+    })
+}
+
+/// Describes a snake that kept moving since `old` with its trimmed tail and new head cells,
+/// none if it did not just move, as when it respawned
+fn moves(old: &SnakeState, new: &SnakeState) -> Option<SnakeChange> {
+    if old.team != new.team {
+        return None;
+    }
+
+    // Trimming the whole snake would cost as much as sending it again
+    let max_trimmed = old.cells.len().min(u8::MAX as usize + 1);
+
+    (0..max_trimmed).find_map(|trimmed| {
+        let kept = &old.cells[trimmed..];
+        let added = new.cells.len().checked_sub(kept.len())?;
+
+        (added <= u8::MAX as usize && new.cells.starts_with(kept)).then(|| SnakeChange::Moved {
+            trimmed: trimmed as u8,
+            cells: new.cells[kept.len()..].to_vec(),
+        })
+    })
+}
+
+impl Snapshot {
+    /// The whole snapshot, for clients without a base
+    pub fn keyframe(&self) -> SnapshotUpdate {
+        SnapshotUpdate {
+            seq: self.seq,
+            base: None,
+            changes: self
+                .snakes
+                .iter()
+                .map(|(id, snake)| (*id, SnakeChange::Spawned(snake.clone())))
+                .collect(),
+        }
+    }
+
+    /// What changed since `base`, with the moves packed as steps if the client reads them
+    pub fn delta(&self, base: &Snapshot, packed_steps: bool) -> SnapshotUpdate {
+        let removed = base
+            .snakes
+            .keys()
+            .filter(|id| !self.snakes.contains_key(id))
+            .map(|id| (*id, SnakeChange::Removed));
+
+        let changed = self.snakes.iter().filter_map(|(id, snake)| {
+            let change = match base.snakes.get(id) {
+                Some(old) if old == snake => return None,
+                Some(old) => moves(old, snake).map(|change| match packed_steps {
+                    true => steps(old, &change).unwrap_or(change),
+                    false => change,
+                }),
+                None => None,
+            };
+
+            Some((
+                *id,
+                change.unwrap_or_else(|| SnakeChange::Spawned(snake.clone())),
+            ))
+        });
+
+        SnapshotUpdate {
+            seq: self.seq,
+            base: Some(base.seq),
+            changes: removed.chain(changed).collect(),
+        }
+    }
+
+    /// Builds the snapshot an update describes from its base, which must be given unless
+    /// the update is a keyframe. None if the update does not fit the base, or leaves a snake
+    /// without cells.
+    pub fn apply(base: Option<&Snapshot>, update: &SnapshotUpdate) -> Option<Snapshot> {
+        let mut snakes = match (update.base, base) {
+            (None, _) => BTreeMap::new(),
+            (Some(seq), Some(base)) if base.seq == seq => base.snakes.clone(),
+            _ => return None,
+        };
+
+        for (id, change) in update.changes.iter() {
+            match change {
+                SnakeChange::Removed => {
+                    snakes.remove(id)?;
+                }
+                SnakeChange::Spawned(snake) => {
+                    // A snake has at least its head
+                    if snake.cells.is_empty() {
+                        return None;
+                    }
+
+                    snakes.insert(*id, snake.clone());
+                }
+                SnakeChange::Moved { trimmed, cells } => {
+                    let snake = snakes.get_mut(id)?;
+
+                    if *trimmed as usize > snake.cells.len() {
+                        return None;
+                    }
+
+                    snake.cells.drain(..*trimmed as usize);
+                    snake.cells.extend_from_slice(cells);
+
+                    if snake.cells.is_empty() {
+                        return None;
+                    }
+                }
+                SnakeChange::Stepped { trimmed, steps } => {
+                    let snake = snakes.get_mut(id)?;
+
+                    if *trimmed as usize > snake.cells.len() {
+                        return None;
+                    }
+
+                    let mut head = *snake.cells.last()?;
+
+                    snake.cells.drain(..*trimmed as usize);
+
+                    for step in steps.iter() {
+                        head = step.apply(head);
+                        snake.cells.push(head);
+                    }
+
+                    if snake.cells.is_empty() {
+                        return None;
+                    }
+                }
+            }
+        }
+
+        Some(Snapshot {
+            seq: update.seq,
+            snakes,
+        })
+    }
+}
+
+fn write_cells(packet: &mut PacketBuilder, cells: &[Point]) {
+    for Point(x, y) in cells.iter() {
+        packet.write(*x as u8);
+        packet.write(*y as u8);
+    }
+}
+
+fn read_cells(packet: &mut ReadablePacket, count: usize) -> Option<Vec<Point>> {
+    if packet.remaining() < count * 2 {
+        return None;
+    }
+
+    Some(
+        (0..count)
+            .map(|_| Point(packet.read() as i32, packet.read() as i32))
+            .collect(),
+    )
+}
+
+impl SnapshotUpdate {
+    pub fn to_packet(&self) -> Vec<u8> {
+        self.builder().build()
+    }
+
+    /// Like `to_packet`, compressed and fragmented if the client reads such frames
+    pub fn to_packet_for(&self, capabilities: u8) -> Vec<u8> {
+        self.builder().build_for(capabilities)
+    }
+
+    fn builder(&self) -> PacketBuilder {
+        let mut packet = PacketBuilder::new(PacketType::Snapshot);
+
+        packet.write_u32_le(self.seq);
+        packet.write_u32_le(self.base.unwrap_or(0));
+
+        for (id, change) in self.changes.iter() {
+            packet.write(*id);
+
+            match change {
+                SnakeChange::Removed => packet.write(0),
+                SnakeChange::Spawned(snake) => {
+                    packet.write(1);
+                    packet.write(snake.team);
+                    packet.write_u16_le(snake.cells.len() as u16);
+                    write_cells(&mut packet, &snake.cells);
+                }
+                SnakeChange::Moved { trimmed, cells } => {
+                    packet.write(2);
+                    packet.write(*trimmed);
+                    packet.write(cells.len() as u8);
+                    write_cells(&mut packet, cells);
+                }
+                SnakeChange::Stepped { trimmed, steps } => {
+                    packet.write(3);
+                    packet.write(*trimmed);
+                    packet.write(steps.len() as u8);
+                    packet.write_steps(steps);
+                }
+            }
+        }
+
+        packet
+    }
+
+    /// Reads a Snapshot packet, none if it is malformed
+    pub fn from_packet(packet: &mut ReadablePacket) -> Option<Self> {
+        if packet.r#type != PacketType::Snapshot {
+            return None;
+        }
+
+        let seq = packet.try_read_u32_le()?;
+        let base = Some(packet.try_read_u32_le()?).filter(|base| *base != 0);
+        let mut changes = Vec::new();
+
+        while packet.remaining() > 0 {
+            let id = packet.try_read()?;
+
+            let change = match packet.try_read()? {
+                0 => SnakeChange::Removed,
+                1 => {
+                    let team = packet.try_read()?;
+                    let count = packet.try_read_u16_le()? as usize;
+
+                    SnakeChange::Spawned(SnakeState {
+                        team,
+                        cells: read_cells(packet, count)?,
+                    })
+                }
+                2 => {
+                    let trimmed = packet.try_read()?;
+                    let count = packet.try_read()? as usize;
+
+                    SnakeChange::Moved {
+                        trimmed,
+                        cells: read_cells(packet, count)?,
+                    }
+                }
+                3 => {
+                    let trimmed = packet.try_read()?;
+                    let count = packet.try_read()? as usize;
+
+                    SnakeChange::Stepped {
+                        trimmed,
+                        steps: packet.try_read_steps(count)?,
+                    }
+                }
+                _ => return None,
+            };
+
+            changes.push((id, change));
+        }
+
+        Some(Self { seq, base, changes })
+    }
+}
+
+/// Tells the server the client has a snapshot, deltas are made against it from then on.
+/// Acknowledging 0 asks for a keyframe.
+pub fn ack_packet(seq: u32) -> Vec<u8> {
+    let mut packet = PacketBuilder::with_capacity(PacketType::SnapshotAck, 4);
+    packet.write_u32_le(seq);
+
+    packet.build()
+}
+
+/// The last snapshots, oldest first
+#[derive(Default)]
+pub struct SnapshotHistory {
+    snapshots: VecDeque<Snapshot>,
+}
+
+impl SnapshotHistory {
+    pub fn push(&mut self, snapshot: Snapshot) {
+        if self.snapshots.len() >= SNAPSHOT_HISTORY {
+            self.snapshots.pop_front();
+        }
+
+        self.snapshots.push_back(snapshot);
+    }
+
+    pub fn get(&self, seq: u32) -> Option<&Snapshot> {
+        self.snapshots
+            .iter()
+            .rev()
+            .find(|snapshot| snapshot.seq == seq)
+    }
+
+    pub fn latest(&self) -> Option<&Snapshot> {
+        self.snapshots.back()
+    }
+}
@@ -0,0 +1,101 @@
+use crate::{
+    mode::GameMode,
+    packet::{PacketBuilder, PacketType, ReadablePacket},
+};
+
+/// Written instead of the ping of players that did not answer yet
+const UNKNOWN_PING: u16 = u16::MAX;
+
+/// A player of the server, in a status response
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerStatus {
+    pub nickname: String,
+    pub snake_id: u8,
+    /// Team of the snake, 0 if it plays alone
+    pub team: u8,
+    pub score: u16,
+    pub length: u16,
+    /// Round-trip time in milliseconds, none until the first pong
+    pub ping: Option<u16>,
+}
+
+/// Answer to a status query, describing the server without joining it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerStatus {
+    pub name: String,
+    pub version: String,
+    pub mode: GameMode,
+    /// Map file name, `default` for the empty arena
+    pub map: String,
+    pub width: u8,
+    pub height: u8,
+    /// Ticks per second
+    pub tick_rate: u8,
+    /// Seconds since the server started
+    pub uptime: u32,
+    pub max_players: u8,
+    pub spectators: u8,
+    pub players: Vec<PlayerStatus>,
+}
+
+impl ServerStatus {
+    pub fn to_packet(&self) -> Vec<u8> {
+        let mut packet = PacketBuilder::new(PacketType::StatusResponse);
+
+        packet.write_str(&self.name);
+        packet.write_str(&self.version);
+        packet.write(self.mode.to_u8());
+        packet.write_str(&self.map);
+        packet.write(self.width);
+        packet.write(self.height);
+        packet.write(self.tick_rate);
+        packet.write_u32_le(self.uptime);
+        packet.write(self.max_players);
+        packet.write(self.spectators);
+
+        for player in self.players.iter() {
+            packet.write_str(&player.nickname);
+            packet.write(player.snake_id);
+            packet.write(player.team);
+            packet.write_u16_le(player.score);
+            packet.write_u16_le(player.length);
+            packet.write_u16_le(player.ping.unwrap_or(UNKNOWN_PING));
+        }
+
+        packet.build()
+    }
+
+    /// Reads a Status response, none if it is malformed
+    pub fn from_packet(packet: &mut ReadablePacket) -> Option<Self> {
+        if packet.r#type != PacketType::StatusResponse {
+            return None;
+        }
+
+        let mut status = Self {
+            name: packet.try_read_str()?,
+            version: packet.try_read_str()?,
+            mode: GameMode::from_u8(packet.try_read()?)?,
+            map: packet.try_read_str()?,
+            width: packet.try_read()?,
+            height: packet.try_read()?,
+            tick_rate: packet.try_read()?,
+            uptime: packet.try_read_u32_le()?,
+            max_players: packet.try_read()?,
+            spectators: packet.try_read()?,
+            players: Vec::new(),
+        };
+
+        while packet.remaining() > 0 {
+            status.players.push(PlayerStatus {
+                nickname: packet.try_read_str()?,
+                snake_id: packet.try_read()?,
+                team: packet.try_read()?,
+                score: packet.try_read_u16_le()?,
+                length: packet.try_read_u16_le()?,
+                ping: Some(packet.try_read_u16_le()?).filter(|ping| *ping != UNKNOWN_PING),
+            });
+        }
+
+        Some(status)
+    }
+}
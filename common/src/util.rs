@@ -1,6 +1,6 @@
 use std::ops::Add;
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Point(pub i32, pub i32);
 
 impl Add<Point> for Point {
@@ -0,0 +1,77 @@
+use std::net::IpAddr;
+
+use common::{
+    discovery::{probe_packet, ServerInfo, ServerList},
+    mode::GameMode,
+    packet::{PacketType, ReadablePacket},
+};
+
+fn server_info() -> ServerInfo {
+    ServerInfo {
+        name: "LAN party".to_string(),
+        players: 3,
+        max_players: 8,
+        mode: GameMode::CaptureTheFlag,
+        port: 14300,
+    }
+}
+
+#[test]
+fn server_info_round_trip() {
+    let bytes = server_info().to_packet();
+    let mut packet = ReadablePacket::try_from_bytes(&bytes[2..]).unwrap();
+
+    assert_eq!(ServerInfo::from_packet(&mut packet), Some(server_info()));
+}
+
+#[test]
+fn malformed_server_info() {
+    let bytes = server_info().to_packet();
+
+    let mut truncated = ReadablePacket::try_from_bytes(&bytes[2..bytes.len() - 1]).unwrap();
+    assert_eq!(ServerInfo::from_packet(&mut truncated), None);
+
+    let mut probe = ReadablePacket::try_from_bytes(&probe_packet()[2..]).unwrap();
+    assert!(probe.r#type == PacketType::DiscoveryRequest);
+    assert_eq!(ServerInfo::from_packet(&mut probe), None);
+
+    assert!(ReadablePacket::try_from_bytes(&[0xee, 0x1]).is_none());
+    assert!(ReadablePacket::try_from_bytes(&[]).is_none());
+}
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+fn addresses(list: &ServerList) -> Vec<&str> {
+    list.servers()
+        .iter()
+        .map(|server| server.address.as_str())
+        .collect()
+}
+
+#[test]
+fn local_server_is_listed_once() {
+    let mut list = ServerList::new(Some(ip("192.168.1.5")));
+
+    // A server on this machine answers both the broadcast and the loopback probes
+    list.add(ip("192.168.1.5"), server_info());
+    list.add(ip("127.0.0.1"), server_info());
+    list.add(ip("192.168.1.5"), server_info());
+
+    assert_eq!(addresses(&list), ["127.0.0.1:14300"]);
+}
+
+#[test]
+fn servers_of_other_machines_are_all_listed() {
+    let mut list = ServerList::new(Some(ip("192.168.1.5")));
+
+    list.add(ip("192.168.1.7"), server_info());
+    list.add(ip("192.168.1.8"), server_info());
+    list.add(ip("192.168.1.7"), server_info());
+
+    assert_eq!(addresses(&list), ["192.168.1.7:14300", "192.168.1.8:14300"]);
+
+    list.clear();
+    assert!(list.servers().is_empty());
+}
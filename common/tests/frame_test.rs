@@ -0,0 +1,142 @@
+use common::{
+    frame::{Reassembler, COMPRESSED, FRAGMENT, MAX_FRAME_LEN, MAX_PACKET_LEN},
+    packet::{PacketBuilder, PacketType, ReadablePacket, CAP_COMPRESSION},
+};
+
+/// Splits a stream into frames without their length, as a receiver would
+fn split_frames(mut bytes: &[u8]) -> Vec<Vec<u8>> {
+    let mut frames = Vec::new();
+
+    while let [low, high, rest @ ..] = bytes {
+        let size = u16::from_le_bytes([*low, *high]) as usize;
+
+        frames.push(rest[..size].to_vec());
+        bytes = &rest[size..];
+    }
+
+    frames
+}
+
+fn map_packet(size: usize) -> PacketBuilder {
+    let mut packet = PacketBuilder::new(PacketType::MapData);
+
+    for i in 0..size {
+        packet.write((i % 7) as u8);
+    }
+
+    packet
+}
+
+#[test]
+fn small_packets_are_left_alone() {
+    let packet = map_packet(10);
+
+    assert_eq!(packet.build_for(CAP_COMPRESSION), packet.build());
+}
+
+#[test]
+fn clients_without_the_capability_get_plain_frames() {
+    let packet = map_packet(1000);
+
+    assert_eq!(packet.build_for(0), packet.build());
+}
+
+#[test]
+fn compressed_round_trip() {
+    let bytes = map_packet(1000).build_for(CAP_COMPRESSION);
+
+    assert!(bytes.len() < 1000);
+    assert_eq!(bytes[2], 0x9 | COMPRESSED);
+
+    let packet = ReadablePacket::try_from_server_bytes(&bytes[2..]).unwrap();
+
+    assert!(packet.r#type == PacketType::MapData);
+    assert_eq!(packet.buffer, map_packet(1000).build()[3..]);
+}
+
+#[test]
+fn compressed_frames_only_come_from_the_server() {
+    let bytes = map_packet(1000).build_for(CAP_COMPRESSION);
+
+    assert!(ReadablePacket::try_from_bytes(&bytes[2..]).is_none());
+}
+
+#[test]
+fn incompressible_packets_are_sent_as_is() {
+    let mut packet = PacketBuilder::new(PacketType::MapData);
+    let mut state = 1u32;
+
+    for _ in 0..1000 {
+        state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+        packet.write((state >> 24) as u8);
+    }
+
+    assert_eq!(packet.build_for(CAP_COMPRESSION), packet.build());
+}
+
+#[test]
+fn corrupted_payload() {
+    let mut bytes = map_packet(1000).build_for(CAP_COMPRESSION);
+    bytes.truncate(bytes.len() / 2);
+
+    assert!(ReadablePacket::try_from_server_bytes(&bytes[2..]).is_none());
+}
+
+#[test]
+fn large_packets_are_fragmented() {
+    // Random enough not to compress below a single frame
+    let mut packet = PacketBuilder::new(PacketType::MapData);
+    let mut state = 1u32;
+
+    for _ in 0..150_000 {
+        state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+        packet.write((state >> 24) as u8);
+    }
+
+    let frames = split_frames(&packet.build_for(CAP_COMPRESSION));
+
+    assert_eq!(frames.len(), 3);
+    assert!(frames.iter().all(|frame| frame.len() <= MAX_FRAME_LEN));
+    assert!(frames.iter().all(|frame| frame[0] == FRAGMENT));
+
+    let mut fragments = Reassembler::default();
+    let mut packets: Vec<Vec<u8>> = frames
+        .into_iter()
+        .filter_map(|frame| fragments.push(frame))
+        .collect();
+
+    assert_eq!(packets.len(), 1);
+
+    let packet = ReadablePacket::try_from_server_bytes(&packets.remove(0)).unwrap();
+
+    assert!(packet.r#type == PacketType::MapData);
+    assert_eq!(packet.buffer.len(), 150_000);
+}
+
+#[test]
+fn other_frames_pass_through() {
+    let mut fragments = Reassembler::default();
+
+    assert_eq!(fragments.push(vec![0x1, 0x2]), Some(vec![0x1, 0x2]));
+}
+
+#[test]
+fn oversized_packets_are_dropped() {
+    let mut fragments = Reassembler::default();
+    let chunk = vec![0; MAX_FRAME_LEN - 2];
+
+    for _ in 0..MAX_PACKET_LEN / chunk.len() + 1 {
+        let mut frame = vec![FRAGMENT, 1];
+        frame.extend_from_slice(&chunk);
+
+        assert_eq!(fragments.push(frame), None);
+    }
+
+    assert_eq!(fragments.push(vec![FRAGMENT, 0, 0x1]), None);
+
+    // The next packet is read again
+    assert_eq!(
+        fragments.push(vec![FRAGMENT, 0, 0x1, 0x2]),
+        Some(vec![0x1, 0x2])
+    );
+}
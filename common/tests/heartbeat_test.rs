@@ -0,0 +1,39 @@
+use std::time::Duration;
+
+use common::{
+    heartbeat::{ping_packet, pong_packet, read_timestamp, Clock},
+    packet::{PacketType, ReadablePacket},
+};
+
+#[test]
+fn ping_pong_round_trip() {
+    let bytes = ping_packet(0xdead_beef);
+    let mut ping = ReadablePacket::try_from_bytes(&bytes[2..]).unwrap();
+
+    assert!(ping.r#type == PacketType::Ping);
+    assert_eq!(read_timestamp(&mut ping), Some(0xdead_beef));
+
+    let bytes = pong_packet(7);
+    let mut pong = ReadablePacket::try_from_bytes(&bytes[2..]).unwrap();
+
+    assert!(pong.r#type == PacketType::Pong);
+    assert_eq!(read_timestamp(&mut pong), Some(7));
+}
+
+#[test]
+fn truncated_ping() {
+    let bytes = ping_packet(1);
+    let mut packet = ReadablePacket::try_from_bytes(&bytes[2..bytes.len() - 1]).unwrap();
+
+    assert_eq!(read_timestamp(&mut packet), None);
+}
+
+#[test]
+fn clock_wraps_around() {
+    let clock = Clock::start();
+
+    assert!(clock.since(clock.timestamp()) < Duration::from_secs(1));
+    // A timestamp taken right before the counter wrapped
+    let elapsed = clock.since(u32::MAX);
+    assert!(elapsed >= Duration::from_millis(1) && elapsed < Duration::from_secs(1));
+}
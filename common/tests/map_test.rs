@@ -0,0 +1,82 @@
+use common::{
+    map::{EdgeMode, Map, MAX_WALLS},
+    util::Point,
+};
+
+#[test]
+fn parse_map() {
+    let map = Map::parse(
+        "// A tiny map
+edges = kill
+
+#####
+#S..#
+#####",
+    )
+    .unwrap();
+
+    assert_eq!(map.width, 5);
+    assert_eq!(map.height, 3);
+    assert_eq!(map.edges, EdgeMode::Kill);
+    assert_eq!(map.walls.len(), 12);
+    assert_eq!(map.spawns, vec![Point(1, 1)]);
+    assert!(map.is_wall(&Point(4, 1)));
+    assert!(!map.is_wall(&Point(2, 1)));
+}
+
+#[test]
+fn parse_invalid_map() {
+    assert!(Map::parse("").is_err());
+    assert!(Map::parse("edges = bounce\n...").is_err());
+    assert!(Map::parse("...\n..").is_err());
+    assert!(Map::parse("..x").is_err());
+}
+
+#[test]
+fn parse_map_with_too_many_walls() {
+    let row = "#".repeat(255);
+
+    assert!(Map::parse(&vec![row.clone(); 255].join("\n")).is_err());
+
+    let rows = MAX_WALLS / 255;
+    let map = Map::parse(&vec![row; rows].join("\n")).unwrap();
+
+    assert_eq!(map.walls.len(), rows * 255);
+}
+
+#[test]
+fn wrap_points() {
+    let mut map = Map::parse("...\n...").unwrap();
+
+    assert_eq!(map.wrap(Point(-1, 0)), Point(2, 0));
+    assert_eq!(map.wrap(Point(1, 2)), Point(1, 0));
+
+    map.edges = EdgeMode::Kill;
+
+    assert_eq!(map.wrap(Point(-1, 0)), Point(-1, 0));
+    assert!(!map.contains(&Point(-1, 0)));
+}
+
+#[test]
+fn shrink_bounds() {
+    let map = Map::parse("......\n......\n......\n......").unwrap();
+    let mut bounds = map.bounds();
+
+    assert!(bounds.shrink(2));
+    assert_eq!(bounds.min, Point(1, 1));
+    assert_eq!(bounds.max, Point(4, 2));
+    assert!(!bounds.contains(&Point(0, 1)));
+    assert!(bounds.contains(&Point(4, 2)));
+
+    assert!(!bounds.shrink(2));
+    assert_eq!(bounds.min, Point(1, 1));
+}
+
+#[test]
+fn parse_bases() {
+    let map = Map::parse("1...\n...2").unwrap();
+
+    assert_eq!(map.bases.get(&1), Some(&Point(0, 0)));
+    assert_eq!(map.bases.get(&2), Some(&Point(3, 1)));
+    assert_eq!(map.bases.get(&3), None);
+}
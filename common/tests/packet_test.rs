@@ -1,4 +1,7 @@
-use common::packet::{PacketBuilder, PacketType, ReadablePacket};
+use common::{
+    packet::{PacketBuilder, PacketType, ReadablePacket, Step},
+    util::Point,
+};
 
 #[test]
 fn readable_packet() {
@@ -23,3 +26,119 @@ fn packet_builder() {
 
     assert_eq!(packet, vec![4, 0, 0x1, 0x3, 8, 0]);
 }
+
+#[test]
+fn u32_round_trip() {
+    let mut packet = PacketBuilder::new(PacketType::Info);
+
+    packet.write_u32_le(0x01020304);
+
+    let bytes = packet.build();
+
+    assert_eq!(bytes, vec![5, 0, 0x1, 4, 3, 2, 1]);
+
+    let mut packet = ReadablePacket::from_bytes(&bytes[2..]);
+
+    assert_eq!(packet.try_read_u32_le(), Some(0x01020304));
+    assert_eq!(packet.try_read_u32_le(), None);
+}
+
+#[test]
+fn u64_round_trip() {
+    let mut packet = PacketBuilder::new(PacketType::Session);
+
+    packet.write_u64_le(0x0102030405060708);
+
+    let bytes = packet.build();
+
+    assert_eq!(bytes, vec![9, 0, 0x1b, 8, 7, 6, 5, 4, 3, 2, 1]);
+
+    let mut packet = ReadablePacket::from_bytes(&bytes[2..]);
+
+    assert_eq!(packet.try_read_u64_le(), Some(0x0102030405060708));
+    assert_eq!(packet.try_read_u64_le(), None);
+}
+
+#[test]
+fn string_round_trip() {
+    let mut packet = PacketBuilder::new(PacketType::Join);
+
+    packet.write_str("snäke");
+    packet.write_str(&"a".repeat(300));
+
+    let bytes = packet.build();
+    let mut packet = ReadablePacket::from_bytes(&bytes[2..]);
+
+    assert_eq!(packet.read_str(), "snäke");
+    assert_eq!(packet.read_str(), "a".repeat(255));
+    assert_eq!(packet.remaining(), 0);
+}
+
+#[test]
+fn checked_reads() {
+    let mut packet = ReadablePacket::from_bytes(&[0x1, 0x2, 0x3, 0x4, 0x5, 0x6]);
+
+    assert_eq!(packet.try_read_u64_le(), None);
+    assert_eq!(packet.try_read_u32_le(), Some(0x05040302));
+    assert_eq!(packet.try_read_u16_le(), None);
+    assert_eq!(packet.try_read_str(), None);
+    assert_eq!(packet.try_read(), Some(0x6));
+    assert_eq!(packet.try_read(), None);
+}
+
+#[test]
+fn steps_round_trip() {
+    let all = [Step::Up, Step::Down, Step::Left, Step::Right];
+
+    for count in 0..10 {
+        let steps: Vec<Step> = all
+            .iter()
+            .cycle()
+            .skip(count)
+            .take(count)
+            .cloned()
+            .collect();
+
+        let mut packet = PacketBuilder::new(PacketType::Snapshot);
+        packet.write_steps(&steps);
+        packet.write(0xaa);
+
+        let bytes = packet.build();
+        assert_eq!(bytes.len(), 3 + count.div_ceil(4) + 1);
+
+        let mut packet = ReadablePacket::from_bytes(&bytes[2..]);
+
+        assert_eq!(packet.try_read_steps(count), Some(steps));
+        assert_eq!(packet.read(), 0xaa);
+    }
+}
+
+#[test]
+fn truncated_steps() {
+    let mut packet = PacketBuilder::new(PacketType::Snapshot);
+    packet.write_steps(&[Step::Left; 5]);
+
+    let bytes = packet.build();
+    let mut packet = ReadablePacket::from_bytes(&bytes[2..]);
+
+    assert_eq!(packet.try_read_steps(9), None);
+}
+
+#[test]
+fn steps_between_cells() {
+    let from = Point(5, 5);
+
+    for step in [Step::Up, Step::Down, Step::Left, Step::Right] {
+        assert_eq!(Step::between(from, step.apply(from)), Some(step));
+    }
+
+    assert_eq!(Step::between(from, Point(6, 6)), None);
+    assert_eq!(Step::between(from, from), None);
+}
+
+#[test]
+fn retired_types_are_unknown() {
+    for type_byte in [0x4, 0x5, 0xf] {
+        assert!(ReadablePacket::try_from_bytes(&[type_byte, 0x1]).is_none());
+    }
+}
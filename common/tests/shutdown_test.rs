@@ -0,0 +1,31 @@
+use std::time::Duration;
+
+use common::{packet::ReadablePacket, shutdown::ShutdownNotice};
+
+#[test]
+fn shutdown_notice_round_trip() {
+    for restart_in in [None, Some(Duration::from_secs(90))] {
+        let notice = ShutdownNotice {
+            reason: "Restarting for an update".to_string(),
+            restart_in,
+        };
+
+        let bytes = notice.to_packet();
+        let mut packet = ReadablePacket::from_bytes(&bytes[2..]);
+
+        assert_eq!(ShutdownNotice::from_packet(&mut packet), Some(notice));
+    }
+}
+
+#[test]
+fn truncated_shutdown_notice() {
+    let notice = ShutdownNotice {
+        reason: "Maintenance".to_string(),
+        restart_in: None,
+    };
+
+    let bytes = notice.to_packet();
+    let mut packet = ReadablePacket::from_bytes(&bytes[2..bytes.len() - 1]);
+
+    assert_eq!(ShutdownNotice::from_packet(&mut packet), None);
+}
@@ -0,0 +1,219 @@
+use std::collections::BTreeMap;
+
+use common::{
+    packet::{ReadablePacket, Step},
+    snapshot::{SnakeChange, SnakeState, Snapshot, SnapshotHistory, SnapshotUpdate},
+    util::Point,
+};
+
+fn snake(team: u8, cells: &[(i32, i32)]) -> SnakeState {
+    SnakeState {
+        team,
+        cells: cells.iter().map(|(x, y)| Point(*x, *y)).collect(),
+    }
+}
+
+fn snapshot(seq: u32, snakes: Vec<(u8, SnakeState)>) -> Snapshot {
+    Snapshot {
+        seq,
+        snakes: BTreeMap::from_iter(snakes),
+    }
+}
+
+fn round_trip(update: &SnapshotUpdate) -> SnapshotUpdate {
+    let bytes = update.to_packet();
+    let mut packet = ReadablePacket::from_bytes(&bytes[2..]);
+
+    SnapshotUpdate::from_packet(&mut packet).unwrap()
+}
+
+#[test]
+fn keyframe_round_trip() {
+    let current = snapshot(
+        7,
+        vec![
+            (1, snake(0, &[(1, 1), (2, 1), (3, 1)])),
+            (4, snake(2, &[(9, 9), (9, 10)])),
+        ],
+    );
+
+    let update = round_trip(&current.keyframe());
+
+    assert_eq!(update.base, None);
+    assert_eq!(Snapshot::apply(None, &update), Some(current));
+}
+
+#[test]
+fn delta_of_moving_snakes() {
+    let base = snapshot(
+        1,
+        vec![
+            (1, snake(0, &[(1, 1), (2, 1), (3, 1)])),
+            (2, snake(0, &[(5, 5), (5, 6)])),
+            (3, snake(0, &[(8, 8), (8, 9)])),
+        ],
+    );
+    let current = snapshot(
+        3,
+        vec![
+            // Moved twice
+            (1, snake(0, &[(3, 1), (4, 1), (5, 1)])),
+            // Grew while moving
+            (2, snake(0, &[(5, 5), (5, 6), (5, 7)])),
+            // Snake 3 died, snake 5 joined
+            (5, snake(1, &[(0, 0), (1, 0)])),
+        ],
+    );
+
+    let update = round_trip(&current.delta(&base, false));
+
+    assert_eq!(update.base, Some(1));
+    assert_eq!(
+        update.changes,
+        vec![
+            (3, SnakeChange::Removed),
+            (
+                1,
+                SnakeChange::Moved {
+                    trimmed: 2,
+                    cells: vec![Point(4, 1), Point(5, 1)]
+                }
+            ),
+            (
+                2,
+                SnakeChange::Moved {
+                    trimmed: 0,
+                    cells: vec![Point(5, 7)]
+                }
+            ),
+            (5, SnakeChange::Spawned(snake(1, &[(0, 0), (1, 0)]))),
+        ]
+    );
+    assert_eq!(Snapshot::apply(Some(&base), &update), Some(current));
+}
+
+#[test]
+fn delta_with_packed_steps() {
+    let base = snapshot(
+        1,
+        vec![
+            (1, snake(0, &[(1, 1), (2, 1), (3, 1)])),
+            (2, snake(0, &[(78, 5), (79, 5)])),
+        ],
+    );
+    let current = snapshot(
+        2,
+        vec![
+            (1, snake(0, &[(3, 1), (3, 2), (2, 2)])),
+            // Wrapped around the arena, which steps can not tell
+            (2, snake(0, &[(79, 5), (0, 5)])),
+        ],
+    );
+
+    let update = round_trip(&current.delta(&base, true));
+
+    assert_eq!(
+        update.changes,
+        vec![
+            (
+                1,
+                SnakeChange::Stepped {
+                    trimmed: 2,
+                    steps: vec![Step::Down, Step::Left]
+                }
+            ),
+            (
+                2,
+                SnakeChange::Moved {
+                    trimmed: 1,
+                    cells: vec![Point(0, 5)]
+                }
+            ),
+        ]
+    );
+    assert_eq!(Snapshot::apply(Some(&base), &update), Some(current));
+}
+
+#[test]
+fn unchanged_snakes_are_left_out() {
+    let base = snapshot(1, vec![(1, snake(0, &[(1, 1), (2, 1)]))]);
+    let current = snapshot(2, base.snakes.clone().into_iter().collect());
+
+    let update = current.delta(&base, false);
+
+    assert!(update.changes.is_empty());
+    assert_eq!(Snapshot::apply(Some(&base), &update), Some(current));
+}
+
+#[test]
+fn respawned_snake_is_sent_again() {
+    let base = snapshot(1, vec![(1, snake(0, &[(1, 1), (2, 1)]))]);
+    let current = snapshot(2, vec![(1, snake(0, &[(30, 30), (31, 30)]))]);
+
+    let update = current.delta(&base, false);
+
+    assert_eq!(
+        update.changes,
+        vec![(1, SnakeChange::Spawned(snake(0, &[(30, 30), (31, 30)])))]
+    );
+}
+
+#[test]
+fn delta_needs_its_base() {
+    let base = snapshot(1, vec![(1, snake(0, &[(1, 1), (2, 1)]))]);
+    let other = snapshot(2, vec![(1, snake(0, &[(1, 1), (2, 1)]))]);
+    let current = snapshot(3, vec![(1, snake(0, &[(2, 1), (3, 1)]))]);
+
+    let update = current.delta(&base, false);
+
+    assert_eq!(Snapshot::apply(None, &update), None);
+    assert_eq!(Snapshot::apply(Some(&other), &update), None);
+}
+
+#[test]
+fn snakes_without_cells_are_refused() {
+    let base = snapshot(1, vec![(1, snake(0, &[(1, 1), (2, 1)]))]);
+
+    let spawned = SnapshotUpdate {
+        seq: 2,
+        base: None,
+        changes: vec![(2, SnakeChange::Spawned(snake(0, &[])))],
+    };
+    let emptied = SnapshotUpdate {
+        seq: 2,
+        base: Some(1),
+        changes: vec![(
+            1,
+            SnakeChange::Moved {
+                trimmed: 2,
+                cells: Vec::new(),
+            },
+        )],
+    };
+
+    assert_eq!(Snapshot::apply(None, &round_trip(&spawned)), None);
+    assert_eq!(Snapshot::apply(Some(&base), &round_trip(&emptied)), None);
+}
+
+#[test]
+fn truncated_snapshot() {
+    let current = snapshot(1, vec![(1, snake(0, &[(1, 1), (2, 1)]))]);
+
+    let bytes = current.keyframe().to_packet();
+    let mut packet = ReadablePacket::from_bytes(&bytes[2..bytes.len() - 1]);
+
+    assert_eq!(SnapshotUpdate::from_packet(&mut packet), None);
+}
+
+#[test]
+fn history_forgets_old_snapshots() {
+    let mut history = SnapshotHistory::default();
+
+    for seq in 1..=100 {
+        history.push(snapshot(seq, Vec::new()));
+    }
+
+    assert!(history.get(1).is_none());
+    assert_eq!(history.get(90).map(|snapshot| snapshot.seq), Some(90));
+    assert_eq!(history.latest().map(|snapshot| snapshot.seq), Some(100));
+}
@@ -0,0 +1,57 @@
+use common::{
+    mode::GameMode,
+    packet::ReadablePacket,
+    status::{PlayerStatus, ServerStatus},
+};
+
+fn server_status() -> ServerStatus {
+    ServerStatus {
+        name: "Snake server".to_string(),
+        version: "0.1.0".to_string(),
+        mode: GameMode::Teams,
+        map: "pillars.txt".to_string(),
+        width: 80,
+        height: 60,
+        tick_rate: 20,
+        uptime: 100_000,
+        max_players: 8,
+        spectators: 1,
+        players: vec![
+            PlayerStatus {
+                nickname: "alice".to_string(),
+                snake_id: 1,
+                team: 1,
+                score: 12,
+                length: 15,
+                ping: Some(42),
+            },
+            PlayerStatus {
+                nickname: "bob".to_string(),
+                snake_id: 2,
+                team: 2,
+                score: 300,
+                length: 4,
+                ping: None,
+            },
+        ],
+    }
+}
+
+#[test]
+fn server_status_round_trip() {
+    let bytes = server_status().to_packet();
+    let mut packet = ReadablePacket::from_bytes(&bytes[2..]);
+
+    assert_eq!(
+        ServerStatus::from_packet(&mut packet),
+        Some(server_status())
+    );
+}
+
+#[test]
+fn truncated_server_status() {
+    let bytes = server_status().to_packet();
+    let mut packet = ReadablePacket::from_bytes(&bytes[2..bytes.len() - 1]);
+
+    assert_eq!(ServerStatus::from_packet(&mut packet), None);
+}
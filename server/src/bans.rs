@@ -0,0 +1,293 @@
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    io::ErrorKind,
+    net::IpAddr,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+pub type Bans = Arc<RwLock<BanList>>;
+
+/// Sent to banned peers in the Connection rejected packet
+pub const BANNED_REASON: &str = "You are banned from this server";
+
+/// A banned address range or nickname.
+///
+/// Written as in the ban list file: `1.2.3.4`, `10.0.0.0/8`, `nick <nickname>`.
+#[derive(Clone, PartialEq)]
+pub enum BanRule {
+    /// A range in CIDR notation, a single address when the prefix covers it all
+    Address { ip: IpAddr, prefix: u8 },
+    /// Compared without case
+    Nickname(String),
+}
+
+fn max_prefix(ip: &IpAddr) -> u8 {
+    match ip {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+/// Whether the first `prefix` bits of both addresses are the same
+fn in_range(ip: &IpAddr, network: &IpAddr, prefix: u8) -> bool {
+    // Clients connecting over IPv6 with an IPv4 address still match IPv4 bans
+    match (ip.to_canonical(), network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(*network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(*network) & mask
+        }
+        _ => false,
+    }
+}
+
+impl BanRule {
+    /// Bans a single address
+    pub fn address(ip: IpAddr) -> Self {
+        let ip = ip.to_canonical();
+
+        Self::Address {
+            ip,
+            prefix: max_prefix(&ip),
+        }
+    }
+
+    /// Whether the rule bans a client, the nickname is empty until it joins
+    pub fn matches(&self, ip: &IpAddr, nickname: &str) -> bool {
+        match self {
+            BanRule::Address {
+                ip: network,
+                prefix,
+            } => in_range(ip, network, *prefix),
+            BanRule::Nickname(banned) => banned.to_lowercase() == nickname.to_lowercase(),
+        }
+    }
+}
+
+impl FromStr for BanRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        if let Some(nickname) = s.strip_prefix("nick ") {
+            return Ok(BanRule::Nickname(nickname.trim().to_string()));
+        }
+
+        let invalid =
+            || format!("Invalid ban {s}, expected an IP address, a range or nick <nickname>");
+
+        let Some((ip, prefix)) = s.split_once('/') else {
+            return Ok(BanRule::address(s.parse().map_err(|_| invalid())?));
+        };
+
+        let ip = ip.parse::<IpAddr>().map_err(|_| invalid())?.to_canonical();
+        let prefix = prefix
+            .parse()
+            .ok()
+            .filter(|prefix| *prefix <= max_prefix(&ip))
+            .ok_or_else(invalid)?;
+
+        Ok(BanRule::Address { ip, prefix })
+    }
+}
+
+impl fmt::Display for BanRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BanRule::Address { ip, prefix } if *prefix == max_prefix(ip) => write!(f, "{ip}"),
+            BanRule::Address { ip, prefix } => write!(f, "{ip}/{prefix}"),
+            BanRule::Nickname(nickname) => write!(f, "nick {nickname}"),
+        }
+    }
+}
+
+/// Bans checked when accepting connections and when clients join,
+/// saved to a file with one rule per line
+pub struct BanList {
+    rules: Vec<BanRule>,
+    path: String,
+    /// Addresses banned until an instant, for flooding, never saved
+    temporary: HashMap<IpAddr, Instant>,
+}
+
+impl BanList {
+    /// Loads the ban list, a missing file is an empty list.
+    /// Empty lines and lines starting with `//` are ignored.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(format!("Could not read ban list {path}: {err}")),
+        };
+
+        let rules = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with("//"))
+            .map(|(index, line)| {
+                line.parse()
+                    .map_err(|err| format!("{path}, line {}: {err}", index + 1))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            rules,
+            path: path.to_string(),
+            temporary: HashMap::new(),
+        })
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let text: String = self.rules.iter().map(|rule| format!("{rule}\n")).collect();
+
+        fs::write(&self.path, text)
+            .map_err(|err| format!("Could not save ban list {}: {err}", self.path))
+    }
+
+    pub fn is_banned(&self, ip: &IpAddr, nickname: &str) -> bool {
+        let temporary = self
+            .temporary
+            .get(&ip.to_canonical())
+            .is_some_and(|until| *until > Instant::now());
+
+        temporary || self.rules.iter().any(|rule| rule.matches(ip, nickname))
+    }
+
+    pub fn ban_temporarily(&mut self, ip: IpAddr, duration: Duration) {
+        let now = Instant::now();
+
+        self.temporary.retain(|_, until| *until > now);
+        self.temporary.insert(ip.to_canonical(), now + duration);
+    }
+
+    /// The temporary bans still running, with the time left
+    pub fn temporary(&self) -> impl Iterator<Item = (&IpAddr, Duration)> {
+        let now = Instant::now();
+
+        self.temporary
+            .iter()
+            .filter(move |(_, until)| **until > now)
+            .map(move |(ip, until)| (ip, *until - now))
+    }
+
+    /// Returns false if the rule already exists
+    pub fn add(&mut self, rule: BanRule) -> bool {
+        if self.rules.contains(&rule) {
+            return false;
+        }
+
+        self.rules.push(rule);
+        true
+    }
+
+    /// Also lifts the temporary ban of a single address.
+    /// Returns false if the rule did not exist.
+    pub fn remove(&mut self, rule: &BanRule) -> bool {
+        let count = self.rules.len();
+        self.rules.retain(|existing| existing != rule);
+
+        let temporary = match rule {
+            BanRule::Address { ip, prefix } if *prefix == max_prefix(ip) => {
+                self.temporary.remove(ip).is_some()
+            }
+            _ => false,
+        };
+
+        self.rules.len() != count || temporary
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &BanRule> {
+        self.rules.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    fn rule(s: &str) -> BanRule {
+        s.parse().unwrap()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn ipv4_range() {
+        let range = rule("192.168.1.0/24");
+
+        assert!(range.matches(&ip("192.168.1.20"), ""));
+        assert!(range.matches(&ip("192.168.1.255"), ""));
+        assert!(!range.matches(&ip("192.168.2.20"), ""));
+        assert!(!range.matches(&ip("::1"), ""));
+    }
+
+    #[test]
+    fn ipv4_mapped_address() {
+        assert!(rule("10.0.0.0/8").matches(&ip("::ffff:10.1.2.3"), ""));
+        assert!(rule("::ffff:10.1.2.3").matches(&ip("10.1.2.3"), ""));
+    }
+
+    #[test]
+    fn whole_and_empty_prefixes() {
+        assert!(rule("0.0.0.0/0").matches(&ip("203.0.113.7"), ""));
+        assert!(rule("::/0").matches(&ip("2001:db8::1"), ""));
+
+        assert!(rule("203.0.113.7/32").matches(&ip("203.0.113.7"), ""));
+        assert!(!rule("203.0.113.7/32").matches(&ip("203.0.113.8"), ""));
+        assert!(rule("2001:db8::1/128").matches(&ip("2001:db8::1"), ""));
+        assert!(!rule("2001:db8::1/128").matches(&ip("2001:db8::2"), ""));
+
+        // Written as a single address once the prefix covers it all
+        assert_eq!(rule("203.0.113.7/32").to_string(), "203.0.113.7");
+    }
+
+    #[test]
+    fn invalid_rules() {
+        for invalid in [
+            "10.0.0.0/33",
+            "::/129",
+            "10.0.0.0/",
+            "10.0.0.0/x",
+            "10.0.0",
+            "",
+        ] {
+            assert!(invalid.parse::<BanRule>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn nickname_ignores_case() {
+        assert!(rule("nick Someone").matches(&ip("127.0.0.1"), "SOMEONE"));
+        assert!(!rule("nick Someone").matches(&ip("127.0.0.1"), "Someone else"));
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = env::temp_dir().join(format!("snake-bans-{}.txt", process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut bans = BanList::load(path).unwrap();
+        assert!(bans.add(rule("10.0.0.0/8")));
+        assert!(bans.add(rule("192.168.1.20")));
+        assert!(bans.add(rule("nick Someone")));
+        assert!(!bans.add(rule("nick Someone")));
+        bans.save().unwrap();
+
+        let loaded = BanList::load(path);
+        fs::remove_file(path).unwrap();
+
+        let rules: Vec<String> = loaded.unwrap().iter().map(BanRule::to_string).collect();
+        assert_eq!(rules, ["10.0.0.0/8", "192.168.1.20", "nick Someone"]);
+    }
+}
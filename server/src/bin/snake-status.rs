@@ -0,0 +1,96 @@
+use std::{
+    env,
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use common::{
+    packet::{PacketBuilder, PacketType, ReadablePacket},
+    status::ServerStatus,
+};
+
+const ADDR: &str = "127.0.0.1:14300";
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Asks a server for its status, without joining the game
+fn query(addr: &str) -> io::Result<ServerStatus> {
+    let invalid = |msg: &str| io::Error::new(ErrorKind::InvalidData, msg.to_string());
+
+    let addr = addr
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "Unknown server address"))?;
+
+    let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+
+    stream.write_all(&PacketBuilder::new(PacketType::StatusRequest).build())?;
+
+    let mut size_bytes = [0u8; 2];
+    stream.read_exact(&mut size_bytes)?;
+
+    let mut buffer = vec![0; u16::from_le_bytes(size_bytes) as usize];
+    stream.read_exact(&mut buffer)?;
+
+    let mut packet =
+        ReadablePacket::try_from_server_bytes(&buffer).ok_or(invalid("Unknown packet"))?;
+
+    if packet.r#type == PacketType::ConnRejected {
+        let reason = packet
+            .try_read_str()
+            .unwrap_or_else(|| "The server is full".to_string());
+
+        return Err(io::Error::new(ErrorKind::ConnectionRefused, reason));
+    }
+
+    ServerStatus::from_packet(&mut packet).ok_or(invalid("Invalid status response"))
+}
+
+fn main() -> io::Result<()> {
+    let addr = env::args().nth(1).unwrap_or(ADDR.to_string());
+
+    let status = query(&addr)?;
+    let uptime = status.uptime;
+
+    println!("{} (version {})", status.name, status.version);
+    println!(
+        "Mode: {}, map: {} ({}x{}), {} ticks per second",
+        status.mode.name(),
+        status.map,
+        status.width,
+        status.height,
+        status.tick_rate
+    );
+    println!(
+        "Uptime: {}h {:02}m {:02}s",
+        uptime / 3600,
+        uptime / 60 % 60,
+        uptime % 60
+    );
+    println!(
+        "Players: {}/{}, spectators: {}",
+        status.players.len(),
+        status.max_players,
+        status.spectators
+    );
+
+    for player in status.players.iter() {
+        let team = match player.team {
+            0 => String::new(),
+            team => format!(", team {team}"),
+        };
+
+        let ping = match player.ping {
+            Some(ping) => format!(", ping {ping} ms"),
+            None => String::new(),
+        };
+
+        println!(
+            "  {} (snake {}{team}): score {}, length {}{ping}",
+            player.nickname, player.snake_id, player.score, player.length
+        );
+    }
+
+    Ok(())
+}
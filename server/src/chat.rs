@@ -0,0 +1,153 @@
+use std::{
+    collections::VecDeque,
+    fs,
+    time::{Duration, Instant},
+};
+
+use common::packet::MAX_CHAT_LEN;
+
+/// A client may send this many messages per RATE_LIMIT_WINDOW
+const RATE_LIMIT: usize = 3;
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(5);
+
+/// Decides what happens to a chat message before it is broadcast,
+/// so that servers can plug in their own moderation
+pub trait ChatFilter: Send + Sync {
+    /// The message to broadcast, none to drop it
+    fn filter(&self, message: &str) -> Option<String>;
+}
+
+/// Masks blocked words with asterisks, whatever their case
+#[derive(Default)]
+pub struct Blocklist {
+    /// Lowercase words
+    words: Vec<String>,
+}
+
+impl Blocklist {
+    /// Loads a blocklist file, with one word per line
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Could not read blocklist {path}: {err}"))?;
+
+        Ok(Self {
+            words: text
+                .lines()
+                .map(|word| word.trim().to_lowercase())
+                .filter(|word| !word.is_empty())
+                .collect(),
+        })
+    }
+}
+
+impl ChatFilter for Blocklist {
+    fn filter(&self, message: &str) -> Option<String> {
+        let masked = message
+            .split(' ')
+            .map(|word| {
+                let blocked = self
+                    .words
+                    .iter()
+                    .any(|blocked| word.to_lowercase().contains(blocked.as_str()));
+
+                if blocked {
+                    "*".repeat(word.chars().count())
+                } else {
+                    word.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        Some(masked)
+    }
+}
+
+/// Strips control characters and cuts the message to the maximum length,
+/// none if nothing is left
+pub fn clean_message(message: &str) -> Option<String> {
+    let message: String = message
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_CHAT_LEN)
+        .collect();
+    let message = message.trim();
+
+    (!message.is_empty()).then(|| message.to_string())
+}
+
+/// When a client last chatted, to limit how fast it does
+#[derive(Default)]
+pub struct ChatLimiter {
+    sent: VecDeque<Instant>,
+}
+
+impl ChatLimiter {
+    /// Records a message, returns false if the client is sending too fast
+    pub fn allow(&mut self) -> bool {
+        let now = Instant::now();
+
+        while self
+            .sent
+            .front()
+            .is_some_and(|sent| now.duration_since(*sent) > RATE_LIMIT_WINDOW)
+        {
+            self.sent.pop_front();
+        }
+
+        if self.sent.len() >= RATE_LIMIT {
+            return false;
+        }
+
+        self.sent.push_back(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocklist_ignores_case() {
+        let blocklist = Blocklist {
+            words: vec!["darn".to_string()],
+        };
+
+        assert_eq!(
+            blocklist.filter("Well DARN it, darnit").as_deref(),
+            Some("Well **** it, ******")
+        );
+        assert_eq!(blocklist.filter("all fine").as_deref(), Some("all fine"));
+    }
+
+    #[test]
+    fn clean_message_strips_and_cuts() {
+        assert_eq!(
+            clean_message("hi\u{7}\nthere\t").as_deref(),
+            Some("hithere")
+        );
+        assert_eq!(clean_message(" \u{1b} "), None);
+
+        let long = "a".repeat(MAX_CHAT_LEN + 10);
+        assert_eq!(clean_message(&long).map(|m| m.len()), Some(MAX_CHAT_LEN));
+    }
+
+    #[test]
+    fn limiter_refuses_past_the_rate() {
+        let mut limiter = ChatLimiter::default();
+
+        for _ in 0..RATE_LIMIT {
+            assert!(limiter.allow());
+        }
+
+        assert!(!limiter.allow());
+
+        // Once the window is over, the client may chat again
+        for sent in limiter.sent.iter_mut() {
+            *sent -= RATE_LIMIT_WINDOW + Duration::from_secs(1);
+        }
+
+        assert!(limiter.allow());
+    }
+}
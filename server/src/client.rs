@@ -0,0 +1,264 @@
+use std::{
+    collections::HashMap,
+    io::{self, ErrorKind, Read, Write},
+    net::{Shutdown, SocketAddr},
+    sync::{atomic::Ordering, Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use mio::{net::TcpStream, Token};
+
+use crate::{
+    chat::ChatLimiter,
+    flood::{Budget, Counters},
+};
+
+/// Output waiting for a client that stopped reading, past this it is disconnected
+const MAX_OUTGOING: usize = 1 << 20;
+
+/// Code holding both locks takes the game context first, then the clients, as the game loop does
+pub type Clients = Arc<RwLock<HashMap<Token, Client>>>;
+
+pub struct Client {
+    pub stream: TcpStream,
+    pub addr: SocketAddr,
+    /// Whether the client sent its Join packet and got the game state
+    pub joined: bool,
+    /// Snake of the client, none for spectators
+    pub snake_id: Option<u8>,
+    /// Name picked by the player, set when joining
+    pub nickname: String,
+    /// Token the player reconnects with to get its snake back, none for spectators
+    pub session: Option<u64>,
+    /// Whether the snake waits for the player after the connection drops,
+    /// not when the server closed it on purpose
+    pub resumable: bool,
+    /// Last snapshot the client has, the next ones are sent as deltas against it
+    pub acked: Option<u32>,
+    /// Protocol features both the client and the server support
+    pub capabilities: u8,
+    pub chat: ChatLimiter,
+    /// When the client last sent anything, to notice dead connections
+    pub last_seen: Instant,
+    /// Round-trip time, none until the first pong
+    pub ping: Option<Duration>,
+    /// Received bytes not making a whole frame yet
+    incoming: Vec<u8>,
+    /// Bytes the socket did not take yet, sent once it is writable again
+    outgoing: Vec<u8>,
+    /// How the connection is shut down once the outgoing bytes are sent
+    closing: Option<Shutdown>,
+    packets: Budget,
+    bytes: Budget,
+}
+
+/// Why a client stopped being read
+pub enum ReadError {
+    Closed,
+    Failed(io::Error),
+    /// The client went over its packet or byte budget
+    Flooding,
+}
+
+impl Client {
+    pub fn new(stream: TcpStream, addr: SocketAddr) -> Self {
+        Self {
+            stream,
+            addr,
+            joined: false,
+            snake_id: None,
+            nickname: String::new(),
+            session: None,
+            resumable: true,
+            acked: None,
+            capabilities: 0,
+            chat: ChatLimiter::default(),
+            last_seen: Instant::now(),
+            ping: None,
+            incoming: Vec::new(),
+            outgoing: Vec::new(),
+            closing: None,
+            packets: Budget::packets(),
+            bytes: Budget::bytes(),
+        }
+    }
+
+    /// Reads everything the client sent, returns the whole frames received so far
+    pub fn receive(&mut self, counters: &Counters) -> Result<Vec<Vec<u8>>, ReadError> {
+        let mut chunk = [0u8; 1024];
+
+        // The socket only signals new data once, it has to be drained
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(ReadError::Closed),
+                Ok(size) => {
+                    counters
+                        .bytes_received
+                        .fetch_add(size as u64, Ordering::Relaxed);
+
+                    if !self.bytes.spend(size as f32) {
+                        return Err(ReadError::Flooding);
+                    }
+
+                    self.incoming.extend_from_slice(&chunk[..size]);
+                    self.last_seen = Instant::now();
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(ReadError::Failed(err)),
+            }
+        }
+
+        let mut frames = Vec::new();
+
+        while let [low, high, ..] = self.incoming[..] {
+            let size = u16::from_le_bytes([low, high]) as usize;
+
+            if self.incoming.len() < 2 + size {
+                break;
+            }
+
+            counters.packets_received.fetch_add(1, Ordering::Relaxed);
+
+            if !self.packets.spend(1.0) {
+                return Err(ReadError::Flooding);
+            }
+
+            frames.push(self.incoming[2..2 + size].to_vec());
+            self.incoming.drain(..2 + size);
+        }
+
+        Ok(frames)
+    }
+
+    /// Queues packets behind the ones not sent yet, sending as much as the socket takes.
+    /// Fails if the connection is broken, or the client stopped reading.
+    pub fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+        // The connection is closing, nothing else is sent
+        if self.closing.is_some() {
+            return Ok(());
+        }
+
+        if self.outgoing.len() + packet.len() > MAX_OUTGOING {
+            self.outgoing.clear();
+            let _ = self.stream.shutdown(Shutdown::Both);
+
+            return Err(io::Error::new(
+                ErrorKind::WriteZero,
+                "The client stopped reading",
+            ));
+        }
+
+        self.outgoing.extend_from_slice(packet);
+        self.flush()
+    }
+
+    /// Sends what the socket takes of the queued bytes, the rest waits for the next
+    /// writable event. Shuts the connection down once everything is sent, if it is closing.
+    pub fn flush(&mut self) -> io::Result<()> {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(size) => {
+                    self.outgoing.drain(..size);
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    self.outgoing.clear();
+                    return Err(err);
+                }
+            }
+        }
+
+        if let Some(how) = self.closing {
+            let _ = self.stream.shutdown(how);
+        }
+
+        Ok(())
+    }
+
+    /// Shuts the connection down once the queued bytes are sent
+    pub fn close(&mut self, how: Shutdown) {
+        self.closing = Some(how);
+        let _ = self.flush();
+    }
+
+    /// Whether everything queued was sent
+    pub fn is_flushed(&self) -> bool {
+        self.outgoing.is_empty()
+    }
+}
+
+/// The client owning a snake
+pub fn snake_client(clients: &mut HashMap<Token, Client>, snake_id: u8) -> Option<&mut Client> {
+    clients
+        .values_mut()
+        .find(|client| client.snake_id == Some(snake_id))
+}
+
+/// Sends a packet to every client that joined the game
+pub fn broadcast(clients: &mut HashMap<Token, Client>, packet: &[u8]) {
+    for client in clients.values_mut().filter(|client| client.joined) {
+        let _ = client.send(packet);
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::{
+        net::{self, TcpListener},
+        thread,
+    };
+
+    use super::*;
+
+    /// A client of the server, and the other end of its connection
+    pub fn connected_client() -> (Client, net::TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let peer = net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, addr) = listener.accept().unwrap();
+
+        stream.set_nonblocking(true).unwrap();
+
+        (Client::new(TcpStream::from_std(stream), addr), peer)
+    }
+
+    #[test]
+    fn closing_client_gets_its_queue_first() {
+        let (mut client, mut peer) = connected_client();
+
+        // Sends until the socket stops taking everything, so that some is left queued
+        let mut sent = Vec::new();
+
+        for chunk in 0..1024u32 {
+            let packet: Vec<u8> = (0..16384u32).map(|i| (chunk + i) as u8).collect();
+            client.send(&packet).unwrap();
+            sent.extend_from_slice(&packet);
+
+            if !client.is_flushed() {
+                break;
+            }
+        }
+
+        client.close(Shutdown::Both);
+        assert!(!client.is_flushed());
+
+        // Nothing else goes out once the client is closing
+        client.send(b"late").unwrap();
+
+        let reader = thread::spawn(move || {
+            let mut received = Vec::new();
+            peer.read_to_end(&mut received).unwrap();
+            received
+        });
+
+        while !client.is_flushed() {
+            client.flush().unwrap();
+            thread::yield_now();
+        }
+
+        // The connection only ends once everything queued arrived
+        assert!(reader.join().unwrap() == sent);
+    }
+}
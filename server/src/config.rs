@@ -0,0 +1,136 @@
+use std::time::Duration;
+
+use common::{map::EdgeMode, mode::GameMode};
+
+pub const MAX_TEAMS: u8 = 4;
+pub const MAX_TICK_RATE: u8 = 100;
+
+pub struct Config {
+    /// Name shown to players looking for servers on the LAN
+    pub name: String,
+    pub mode: GameMode,
+    /// Turn the body of a snake that died into food pellets
+    pub corpse_food: bool,
+    /// Map file to load, an empty wrapping arena if not set
+    pub map: Option<String>,
+    /// Overrides the edge mode of the map
+    pub edges: Option<EdgeMode>,
+    /// How often the arena closes a ring in battle royale
+    pub shrink_interval: Duration,
+    /// Number of teams in the team modes
+    pub teams: u8,
+    /// Whether teammates' bodies are lethal
+    pub friendly_fire: bool,
+    /// Spawn power-ups around the arena
+    pub power_ups: bool,
+    /// File listing the words masked in the chat
+    pub chat_blocklist: Option<String>,
+    /// Game updates per second
+    pub tick_rate: u8,
+    /// File the bans are loaded from and saved to
+    pub ban_list: String,
+    /// File the totals of each run are appended to on shutdown
+    pub stats_file: String,
+    /// File the replay of the run is written to on shutdown
+    pub replay_file: String,
+    /// Clients silent for longer are disconnected
+    pub idle_timeout: Duration,
+    /// How long the snake of a disconnected player waits for it to reconnect
+    pub reconnect_grace: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            name: "Snake server".to_string(),
+            mode: GameMode::default(),
+            corpse_food: false,
+            map: None,
+            edges: None,
+            shrink_interval: Duration::from_secs(10),
+            teams: 2,
+            friendly_fire: false,
+            power_ups: false,
+            chat_blocklist: None,
+            tick_rate: 20,
+            ban_list: "bans.txt".to_string(),
+            stats_file: "stats.txt".to_string(),
+            replay_file: "replay.bin".to_string(),
+            idle_timeout: Duration::from_secs(15),
+            reconnect_grace: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Parses a number of ticks per second, also used by the admin console
+pub fn parse_tick_rate(value: &str) -> Result<u8, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|rate| (1..=MAX_TICK_RATE).contains(rate))
+        .ok_or(format!(
+            "The tick rate must be between 1 and {MAX_TICK_RATE}"
+        ))
+}
+
+impl Config {
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut config = Config::default();
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("Missing value for {arg}"));
+
+            match arg.as_str() {
+                "--name" => config.name = value()?,
+                "--mode" => config.mode = value()?.parse()?,
+                "--corpse-food" => config.corpse_food = true,
+                "--map" => config.map = Some(value()?),
+                "--edges" => config.edges = Some(value()?.parse()?),
+                "--shrink-interval" => {
+                    let secs = value()?
+                        .parse()
+                        .ok()
+                        .filter(|secs: &f32| *secs > 0.0)
+                        .ok_or("Invalid value for --shrink-interval")?;
+
+                    config.shrink_interval = Duration::from_secs_f32(secs);
+                }
+                "--teams" => {
+                    config.teams = value()?
+                        .parse()
+                        .ok()
+                        .filter(|teams| (2..=MAX_TEAMS).contains(teams))
+                        .ok_or(format!("--teams must be between 2 and {MAX_TEAMS}"))?;
+                }
+                "--friendly-fire" => config.friendly_fire = true,
+                "--power-ups" => config.power_ups = true,
+                "--chat-blocklist" => config.chat_blocklist = Some(value()?),
+                "--tick-rate" => config.tick_rate = parse_tick_rate(&value()?)?,
+                "--ban-list" => config.ban_list = value()?,
+                "--stats-file" => config.stats_file = value()?,
+                "--replay-file" => config.replay_file = value()?,
+                "--idle-timeout" => {
+                    let secs = value()?
+                        .parse()
+                        .ok()
+                        .filter(|secs: &f32| *secs > 0.0)
+                        .ok_or("Invalid value for --idle-timeout")?;
+
+                    config.idle_timeout = Duration::from_secs_f32(secs);
+                }
+                "--reconnect-grace" => {
+                    let secs = value()?
+                        .parse()
+                        .ok()
+                        .filter(|secs: &f32| *secs >= 0.0)
+                        .ok_or("Invalid value for --reconnect-grace")?;
+
+                    config.reconnect_grace = Duration::from_secs_f32(secs);
+                }
+                _ => return Err(format!("Unknown argument {arg}")),
+            }
+        }
+
+        Ok(config)
+    }
+}
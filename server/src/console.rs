@@ -0,0 +1,358 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead},
+    net::{IpAddr, Shutdown},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    thread,
+    time::Duration,
+};
+
+use common::map::{EdgeMode, Map};
+use mio::Token;
+
+use crate::{
+    bans::{BanRule, Bans, BANNED_REASON},
+    chat::clean_message,
+    chat_packet,
+    client::{broadcast, Client, Clients},
+    config::parse_tick_rate,
+    flood::Counters,
+    game::{tick_interval, GameContext},
+    record_fullstate, reject_client, send_fullstate,
+    shutdown::{ShutdownHandle, SHUTDOWN_REASON},
+};
+
+const HELP: &str = "\
+help                Shows this list
+list                Lists the connected clients
+kick <id|ip>        Disconnects a client, or every client of an address
+ban <id|ip|range>   Bans the address of a client, an address or a range like 10.0.0.0/8
+ban nick <nickname> Bans a nickname
+unban <ban>         Lifts a ban, written as in the bans list
+bans                Lists the bans, temporary ones included
+stats               Shows the connection and traffic counters
+tickrate <rate>     Sets the number of ticks per second
+reset               Starts a new round
+map <file|default>  Switches to another map, starting a new round
+say <message>       Sends a chat message to every client
+shutdown [secs] [reason]
+                    Stops the server, telling the clients why and when it should be back";
+
+/// Admin commands typed on the server's stdin
+pub struct Console {
+    context: Arc<RwLock<GameContext>>,
+    clients: Clients,
+    bans: Bans,
+    counters: Arc<Counters>,
+    /// Overrides the edges of the maps loaded from the console, like at startup
+    edges: Option<EdgeMode>,
+    shutdown: ShutdownHandle,
+}
+
+/// The clients a command targets, by client ID (its token) or IP address
+fn targets(clients: &HashMap<Token, Client>, target: &str) -> Result<Vec<Token>, String> {
+    if let Ok(id) = target.parse() {
+        return match clients.contains_key(&Token(id)) {
+            true => Ok(vec![Token(id)]),
+            false => Err(format!("No client with the ID {id}")),
+        };
+    }
+
+    let ip: IpAddr = target
+        .parse()
+        .map_err(|_| format!("Invalid target {target}, expected a client ID or an IP address"))?;
+
+    Ok(clients
+        .iter()
+        .filter(|(_, client)| client.addr.ip() == ip)
+        .map(|(token, _)| *token)
+        .collect())
+}
+
+impl Console {
+    pub fn new(
+        context: &Arc<RwLock<GameContext>>,
+        clients: &Clients,
+        bans: &Bans,
+        counters: &Arc<Counters>,
+        edges: Option<EdgeMode>,
+        shutdown: ShutdownHandle,
+    ) -> Self {
+        Self {
+            context: Arc::clone(context),
+            clients: Arc::clone(clients),
+            bans: Arc::clone(bans),
+            counters: Arc::clone(counters),
+            edges,
+            shutdown,
+        }
+    }
+
+    /// Reads commands until stdin is closed, on its own thread
+    pub fn spawn(self) {
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+
+                if let Err(err) = self.run(line.trim()) {
+                    eprintln!("ERROR: {err}");
+                }
+            }
+        });
+    }
+
+    fn run(&self, line: &str) -> Result<(), String> {
+        let (command, args) = line
+            .split_once(' ')
+            .map_or((line, ""), |(command, args)| (command, args.trim()));
+
+        match command {
+            "" => Ok(()),
+            "help" => {
+                println!("{HELP}");
+                Ok(())
+            }
+            "list" => {
+                self.list();
+                Ok(())
+            }
+            "kick" => self.kick(args),
+            "ban" => self.ban(args),
+            "unban" => self.unban(args),
+            "bans" => {
+                let bans = self.bans.read().unwrap();
+
+                for rule in bans.iter() {
+                    println!("{rule}");
+                }
+
+                for (ip, left) in bans.temporary() {
+                    println!("{ip} (temporary, {}s left)", left.as_secs());
+                }
+                Ok(())
+            }
+            "stats" => {
+                self.stats();
+                Ok(())
+            }
+            "tickrate" => {
+                let tick_rate = parse_tick_rate(args)?;
+                self.context.write().unwrap().tick_interval = tick_interval(tick_rate);
+
+                println!("INFO: Running at {tick_rate} ticks per second");
+                Ok(())
+            }
+            "reset" => {
+                self.new_round(GameContext::reset_round);
+
+                println!("INFO: New round started");
+                Ok(())
+            }
+            "map" => self.set_map(args),
+            "say" => self.say(args),
+            "shutdown" => {
+                // The restart delay is optional, so is the reason after it
+                let (first, rest) = args.split_once(' ').unwrap_or((args, ""));
+
+                let (restart_in, reason) = match first.parse::<u64>() {
+                    Ok(secs) => (Some(Duration::from_secs(secs)), rest.trim()),
+                    Err(_) => (None, args),
+                };
+
+                let reason = clean_message(reason).unwrap_or_else(|| SHUTDOWN_REASON.to_string());
+                self.shutdown.request(&reason, restart_in);
+                Ok(())
+            }
+            _ => Err(format!("Unknown command {command}, type help for the list")),
+        }
+    }
+
+    fn list(&self) {
+        let context = self.context.read().unwrap();
+        let clients = self.clients.read().unwrap();
+
+        if clients.is_empty() {
+            println!("No clients connected");
+            return;
+        }
+
+        let mut tokens: Vec<_> = clients.keys().collect();
+        tokens.sort();
+
+        for token in tokens {
+            let client = &clients[token];
+
+            let role = match client.snake_id {
+                Some(snake_id) => match context.snakes.get(&snake_id) {
+                    Some(snake) => format!(
+                        "snake {snake_id}, team {}, score {}, length {}",
+                        snake.team,
+                        snake.score,
+                        snake.body.len() + 1
+                    ),
+                    None => format!("snake {snake_id}, dead"),
+                },
+                None if client.joined => "spectator".to_string(),
+                None => "joining".to_string(),
+            };
+
+            let ping = match client.ping {
+                Some(ping) => format!("{} ms", ping.as_millis()),
+                None => "-".to_string(),
+            };
+
+            println!(
+                "{:>4}  {:<16}  {:<21}  {ping:>7}  {role}",
+                token.0, client.nickname, client.addr
+            );
+        }
+    }
+
+    fn stats(&self) {
+        let count = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let counters = &self.counters;
+
+        println!(
+            "Clients: {} connected, {} accepted, {} refused for connecting too fast",
+            self.clients.read().unwrap().len(),
+            count(&counters.connections_accepted),
+            count(&counters.connections_refused)
+        );
+        println!(
+            "Received: {} packets, {} bytes",
+            count(&counters.packets_received),
+            count(&counters.bytes_received)
+        );
+        println!(
+            "Flooding: {} clients kicked, {} temporary bans",
+            count(&counters.flood_kicks),
+            count(&counters.temporary_bans)
+        );
+    }
+
+    /// Disconnects the targeted clients
+    fn kick(&self, target: &str) -> Result<(), String> {
+        if target.is_empty() {
+            return Err("Expected a client ID or an IP address".to_string());
+        }
+
+        let mut clients = self.clients.write().unwrap();
+        let tokens = targets(&clients, target)?;
+
+        if tokens.is_empty() {
+            return Err(format!("No client connected from {target}"));
+        }
+
+        for token in tokens {
+            let client = clients.get_mut(&token).unwrap();
+            reject_client(client, "You were kicked from the server");
+
+            println!("INFO: Kicked client {} ({})", token.0, client.addr);
+        }
+
+        Ok(())
+    }
+
+    /// Adds a ban, disconnecting the clients it matches
+    fn ban(&self, target: &str) -> Result<(), String> {
+        let mut clients = self.clients.write().unwrap();
+
+        let rule = match target.parse() {
+            Ok(id) => {
+                let client = clients
+                    .get(&Token(id))
+                    .ok_or(format!("No client with the ID {id}"))?;
+
+                BanRule::address(client.addr.ip())
+            }
+            Err(_) => target.parse()?,
+        };
+
+        let mut bans = self.bans.write().unwrap();
+
+        if !bans.add(rule.clone()) {
+            return Err(format!("{rule} is already banned"));
+        }
+
+        println!("INFO: Banned {rule}");
+
+        for (token, client) in clients.iter_mut() {
+            if rule.matches(&client.addr.ip(), &client.nickname) {
+                reject_client(client, BANNED_REASON);
+
+                println!("INFO: Kicked client {} ({})", token.0, client.addr);
+            }
+        }
+
+        bans.save()
+    }
+
+    fn unban(&self, rule: &str) -> Result<(), String> {
+        let rule: BanRule = rule.parse()?;
+        let mut bans = self.bans.write().unwrap();
+
+        if !bans.remove(&rule) {
+            return Err(format!("{rule} is not banned"));
+        }
+
+        println!("INFO: Unbanned {rule}");
+        bans.save()
+    }
+
+    fn set_map(&self, path: &str) -> Result<(), String> {
+        let mut map = match path {
+            "" => return Err("Expected a map file, or default".to_string()),
+            "default" => Map::default(),
+            path => Map::load(path)?,
+        };
+
+        if let Some(edges) = self.edges {
+            map.edges = edges;
+        }
+
+        let (width, height) = (map.width, map.height);
+
+        self.new_round(|context| context.set_map(map, path));
+
+        println!("INFO: Playing on {path}, a {width}x{height} map");
+        Ok(())
+    }
+
+    fn say(&self, message: &str) -> Result<(), String> {
+        let message = clean_message(message).ok_or("Expected a message")?;
+
+        let mut clients = self.clients.write().unwrap();
+        broadcast(&mut clients, &chat_packet(0, "Server", &message));
+
+        println!("INFO: Server: {message}");
+        Ok(())
+    }
+
+    /// Changes the game, then sends the whole state again to every client.
+    /// `change` returns the snakes left without room to respawn, their clients are kicked.
+    fn new_round(&self, change: impl FnOnce(&mut GameContext) -> Vec<u8>) {
+        // Both locks are held so that no update of the old round reaches the clients
+        let mut context = self.context.write().unwrap();
+        let mut clients = self.clients.write().unwrap();
+
+        let failed = change(&mut context);
+
+        for client in clients.values_mut().filter(|client| client.joined) {
+            if let Some(snake_id) = client.snake_id.filter(|id| failed.contains(id)) {
+                eprintln!("WARN: No room to respawn snake {snake_id}, kicking its client");
+
+                let _ = client.stream.shutdown(Shutdown::Both);
+                continue;
+            }
+
+            let _ = send_fullstate(client.snake_id.unwrap_or(0), client, &mut context);
+        }
+
+        record_fullstate(&mut context);
+    }
+}
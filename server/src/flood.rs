@@ -0,0 +1,188 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::atomic::AtomicU64,
+    time::{Duration, Instant},
+};
+
+/// Packets per second a connection may send, and how many at once
+const PACKET_RATE: f32 = 50.0;
+const PACKET_BURST: f32 = 100.0;
+/// Bytes per second a connection may send, and how many at once
+const BYTE_RATE: f32 = 4096.0;
+const BYTE_BURST: f32 = 16384.0;
+/// Connections per second an address may open, and how many at once
+const ACCEPT_RATE: f32 = 0.5;
+const ACCEPT_BURST: f32 = 5.0;
+/// Connections refused for going too fast before the address is banned for a while
+const MAX_REFUSED: u32 = 10;
+/// Addresses tracked before the idle ones are forgotten
+const MAX_TRACKED: usize = 1024;
+
+/// How long abusers are banned
+pub const TEMPORARY_BAN: Duration = Duration::from_secs(5 * 60);
+
+/// A token bucket, `rate` tokens come back every second, up to `burst`
+pub struct Budget {
+    available: f32,
+    rate: f32,
+    burst: f32,
+    refilled: Instant,
+}
+
+impl Budget {
+    pub fn new(rate: f32, burst: f32) -> Self {
+        Self {
+            available: burst,
+            rate,
+            burst,
+            refilled: Instant::now(),
+        }
+    }
+
+    pub fn packets() -> Self {
+        Self::new(PACKET_RATE, PACKET_BURST)
+    }
+
+    pub fn bytes() -> Self {
+        Self::new(BYTE_RATE, BYTE_BURST)
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled).as_secs_f32();
+
+        self.available = (self.available + elapsed * self.rate).min(self.burst);
+        self.refilled = now;
+    }
+
+    /// Takes `amount` tokens, returns false if there are not enough left
+    pub fn spend(&mut self, amount: f32) -> bool {
+        self.refill();
+
+        if self.available < amount {
+            return false;
+        }
+
+        self.available -= amount;
+        true
+    }
+
+    fn is_full(&mut self) -> bool {
+        self.refill();
+        self.available >= self.burst
+    }
+}
+
+/// What to do with a new connection
+pub enum Admission {
+    Accept,
+    /// The address connects too fast
+    Refuse,
+    /// The address kept connecting too fast
+    Ban,
+}
+
+struct Attempts {
+    budget: Budget,
+    refused: u32,
+}
+
+/// Limits how fast each address opens connections
+#[derive(Default)]
+pub struct AcceptLimiter {
+    addresses: HashMap<IpAddr, Attempts>,
+}
+
+impl AcceptLimiter {
+    pub fn admit(&mut self, ip: IpAddr) -> Admission {
+        if self.addresses.len() >= MAX_TRACKED {
+            self.addresses
+                .retain(|_, attempts| !attempts.budget.is_full());
+        }
+
+        let attempts = self.addresses.entry(ip).or_insert_with(|| Attempts {
+            budget: Budget::new(ACCEPT_RATE, ACCEPT_BURST),
+            refused: 0,
+        });
+
+        if attempts.budget.spend(1.0) {
+            return Admission::Accept;
+        }
+
+        attempts.refused += 1;
+
+        if attempts.refused >= MAX_REFUSED {
+            attempts.refused = 0;
+            return Admission::Ban;
+        }
+
+        Admission::Refuse
+    }
+}
+
+/// Totals since the server started, shown by the `stats` console command and saved on shutdown
+#[derive(Default)]
+pub struct Counters {
+    pub connections_accepted: AtomicU64,
+    /// Refused for opening connections too fast
+    pub connections_refused: AtomicU64,
+    pub packets_received: AtomicU64,
+    pub bytes_received: AtomicU64,
+    /// Clients disconnected for going over their budget
+    pub flood_kicks: AtomicU64,
+    pub temporary_bans: AtomicU64,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn budget_refuses_past_the_burst() {
+        let mut budget = Budget::new(1.0, 3.0);
+
+        assert!(budget.spend(2.0));
+        assert!(budget.spend(1.0));
+        assert!(!budget.spend(1.0));
+    }
+
+    #[test]
+    fn budget_refills_over_time() {
+        let mut budget = Budget::new(2.0, 10.0);
+        assert!(budget.spend(10.0));
+
+        // As if 2 seconds went by since the last spend
+        budget.refilled -= Duration::from_secs(2);
+
+        assert!(budget.spend(4.0));
+        assert!(!budget.spend(1.0));
+
+        // Never more than the burst
+        budget.refilled -= Duration::from_secs(60);
+        assert!(!budget.spend(11.0));
+        assert!(budget.spend(10.0));
+    }
+
+    #[test]
+    fn fast_connections_are_refused_then_banned() {
+        let mut limiter = AcceptLimiter::default();
+        let ip = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7));
+
+        for _ in 0..ACCEPT_BURST as u32 {
+            assert!(matches!(limiter.admit(ip), Admission::Accept));
+        }
+
+        for _ in 1..MAX_REFUSED {
+            assert!(matches!(limiter.admit(ip), Admission::Refuse));
+        }
+
+        assert!(matches!(limiter.admit(ip), Admission::Ban));
+
+        // Other addresses are not affected
+        let other = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 8));
+        assert!(matches!(limiter.admit(other), Admission::Accept));
+    }
+}
@@ -1,13 +1,41 @@
 use std::{
-    collections::{HashMap, VecDeque},
-    time::{SystemTime, UNIX_EPOCH},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use common::util::Point;
-use rand::{rngs::StdRng, Rng, SeedableRng};
-
-const WIDTH: i32 = 80;
-const HEIGHT: i32 = 60;
+use common::{
+    map::{Bounds, Map},
+    mode::GameMode,
+    power_up::{PowerUp, MAGNET_RADIUS},
+    snapshot::{SnakeState, Snapshot, SnapshotHistory},
+    util::Point,
+};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::{config::Config, replay::Replay};
+
+/// The battle royale arena stops shrinking at this size
+const MIN_ARENA_SIZE: i32 = 10;
+/// Cells moved per tick by a boosting snake
+const MAX_SPEED: u8 = 2;
+/// Body segments a snake needs to boost, it sheds one per boosted tick
+const MIN_BOOST_BODY: usize = 2;
+/// A snake is inside a base when its head is this close to the base center
+const BASE_RADIUS: i32 = 1;
+/// How often a power-up shows up, while there are less than MAX_POWER_UPS around
+const POWER_UP_INTERVAL: Duration = Duration::from_secs(10);
+const MAX_POWER_UPS: usize = 3;
+
+/// How long an effect lasts, a shield also ends once it saved the snake
+fn effect_duration(power_up: PowerUp) -> Duration {
+    match power_up {
+        PowerUp::Invincibility => Duration::from_secs(5),
+        PowerUp::Ghost => Duration::from_secs(5),
+        PowerUp::Magnet => Duration::from_secs(8),
+        PowerUp::Shield => Duration::from_secs(15),
+    }
+}
 
 #[derive(Clone, Copy, PartialEq)]
 pub enum Direction {
@@ -20,151 +48,969 @@ pub enum Direction {
 pub struct Snake {
     pub body: VecDeque<Point>,
     pub head: Point,
+    /// Team of the snake, 0 if it plays alone
+    pub team: u8,
+    /// Food eaten since the snake spawned
+    pub score: u16,
+    pub boosting: bool,
+    /// Active power-up effects, and when they end
+    pub effects: HashMap<PowerUp, Instant>,
+    /// Tail segments still to grow, from food eaten
+    growth: u16,
     direction: Direction,
+    /// Set while its player is reconnecting, the snake is removed once it passes
+    frozen_until: Option<Instant>,
 }
 
 impl Snake {
-    pub fn update_head(&mut self) {
+    /// Moves the head one cell forward, it may end up outside the map if its edges kill
+    pub fn update_head(&mut self, map: &Map) {
         let head = self.head;
 
-        let mut next_head = match self.direction {
+        let next_head = match self.direction {
             Direction::Up => head + Point(0, -1),
             Direction::Down => head + Point(0, 1),
             Direction::Right => head + Point(1, 0),
             Direction::Left => head + Point(-1, 0),
         };
 
-        match next_head {
-            Point(-1, _) => next_head.0 = WIDTH - 1,
-            Point(WIDTH, _) => next_head.0 = 0,
-            Point(_, -1) => next_head.1 = HEIGHT - 1,
-            Point(_, HEIGHT) => next_head.1 = 0,
-            _ => {}
-        };
-
         self.body.push_back(self.head);
-        self.head = next_head;
+        self.head = map.wrap(next_head);
     }
 
     pub fn change_direction(&mut self, direction: Direction) {
         self.direction = direction;
     }
+
+    /// The snake as sent in snapshots
+    pub fn state(&self) -> SnakeState {
+        let mut cells: Vec<Point> = self.body.iter().cloned().collect();
+        cells.push(self.head);
+
+        SnakeState {
+            team: self.team,
+            cells,
+        }
+    }
+
+    pub fn has_effect(&self, power_up: PowerUp) -> bool {
+        self.effects.contains_key(&power_up)
+    }
+
+    /// Cells moved per tick
+    pub fn speed(&self) -> u8 {
+        if self.frozen_until.is_some() {
+            0
+        } else if self.boosting && self.body.len() >= MIN_BOOST_BODY {
+            MAX_SPEED
+        } else {
+            1
+        }
+    }
+}
+
+pub struct Flag {
+    pub team: u8,
+    pub base: Point,
+    /// Snake carrying the flag on its head
+    pub carrier: Option<u8>,
+    /// Where the flag lies when nobody carries it
+    pub position: Point,
+}
+
+impl Flag {
+    fn new(team: u8, base: Point) -> Self {
+        Self {
+            team,
+            base,
+            carrier: None,
+            position: base,
+        }
+    }
+
+    fn return_home(&mut self) {
+        self.carrier = None;
+        self.position = self.base;
+    }
+}
+
+/// Time between two updates at a number of ticks per second
+pub fn tick_interval(tick_rate: u8) -> Duration {
+    Duration::from_secs_f32(1.0 / tick_rate as f32)
+}
+
+/// The file name of a map path, shown in status queries
+fn map_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map_or(path.to_string(), |name| name.to_string_lossy().into_owned())
+}
+
+/// Whether two points are at most `radius` cells apart, diagonals included
+fn is_near(a: &Point, b: &Point, radius: i32) -> bool {
+    (a.0 - b.0).abs() <= radius && (a.1 - b.1).abs() <= radius
+}
+
+/// Everything that happened during a single game tick
+#[derive(Default)]
+pub struct TickEvents {
+    /// Snakes that ate a food, and where that food was
+    pub eaten: Vec<(u8, Point)>,
+    pub spawned_food: Vec<Point>,
+    pub killed: Vec<u8>,
+    /// The new arena bounds, if they changed
+    pub bounds: Option<Bounds>,
+    /// Snakes that brought the enemy flag to their base
+    pub captures: Vec<u8>,
+    pub flags_changed: bool,
+    pub power_ups_changed: bool,
+    /// Effects picked up by snakes, and how long they last
+    pub effects_started: Vec<(u8, PowerUp, Duration)>,
+    pub effects_ended: Vec<(u8, PowerUp)>,
+}
+
+impl TickEvents {
+    pub fn scores_changed(&self) -> bool {
+        !self.eaten.is_empty() || !self.captures.is_empty()
+    }
 }
 
 pub struct GameContext {
     pub snakes: HashMap<u8, Snake>,
-    pub food: Point,
+    pub food: HashSet<Point>,
+    pub map: Map,
+    /// Map file name, `default` for the empty arena
+    pub map_name: String,
+    pub mode: GameMode,
+    /// Cells outside of these are lethal
+    pub bounds: Bounds,
+    /// Score of each team, team 1 being the first
+    pub team_scores: Vec<u16>,
+    /// Flags of capture the flag, one per team
+    pub flags: Vec<Flag>,
+    pub power_ups: HashMap<Point, PowerUp>,
+    power_ups_enabled: bool,
+    last_power_up: Instant,
+    friendly_fire: bool,
+    corpse_food: bool,
+    shrink_interval: Duration,
+    last_shrink: Instant,
+    /// Time between two updates
+    pub tick_interval: Duration,
+    /// Snakes of the players that may reconnect, by session token
+    sessions: HashMap<u64, u8>,
+    /// How long the snake of a disconnected player waits for it
+    pub reconnect_grace: Duration,
+    /// The snapshots sent lately, clients get what changed since the last one they acknowledged
+    pub snapshots: SnapshotHistory,
+    /// The game so far, saved when the server shuts down
+    pub replay: Replay,
     rng: StdRng,
 }
 
 impl GameContext {
-    pub fn new() -> Self {
-        GameContext {
+    pub fn new(config: &Config, map: Map) -> Self {
+        let mut context = GameContext {
             snakes: HashMap::new(),
-            food: Point(10, 4),
+            food: HashSet::new(),
+            bounds: map.bounds(),
+            map,
+            map_name: config
+                .map
+                .as_deref()
+                .map_or("default".to_string(), map_name),
+            mode: config.mode,
+            team_scores: match config.mode {
+                GameMode::Teams => vec![0; config.teams as usize],
+                GameMode::CaptureTheFlag => vec![0; 2],
+                _ => Vec::new(),
+            },
+            flags: Vec::new(),
+            power_ups: HashMap::new(),
+            power_ups_enabled: config.power_ups,
+            last_power_up: Instant::now(),
+            friendly_fire: config.friendly_fire,
+            corpse_food: config.corpse_food,
+            shrink_interval: config.shrink_interval,
+            last_shrink: Instant::now(),
+            tick_interval: tick_interval(config.tick_rate),
+            sessions: HashMap::new(),
+            reconnect_grace: config.reconnect_grace,
+            snapshots: SnapshotHistory::default(),
+            replay: Replay::default(),
             rng: StdRng::seed_from_u64(
                 SystemTime::now()
                     .duration_since(UNIX_EPOCH)
                     .unwrap()
                     .as_secs(),
             ),
+        };
+
+        context.reset_arena();
+
+        context
+    }
+
+    /// Puts the food, flags and bounds back as they are at the start of a round
+    fn reset_arena(&mut self) {
+        self.food.clear();
+        self.power_ups.clear();
+        self.flags.clear();
+        self.bounds = self.map.bounds();
+        self.team_scores.iter_mut().for_each(|score| *score = 0);
+        self.last_shrink = Instant::now();
+        self.last_power_up = Instant::now();
+
+        if self.mode == GameMode::CaptureTheFlag {
+            let (width, height) = (self.map.width, self.map.height);
+            let default_bases = [Point(4, height / 2), Point(width - 5, height / 2)];
+
+            for (team, default_base) in (1..=2).zip(default_bases) {
+                let base = match self.map.bases.get(&team) {
+                    Some(base) => *base,
+                    // The default spot may be a wall of the map
+                    None => self.nearest_free_cell(default_base).unwrap_or(default_base),
+                };
+
+                self.flags.push(Flag::new(team, base));
+            }
         }
+
+        self.spawn_food();
     }
 
-    pub fn spawn_snake(&mut self, snake_id: u8) {
-        let mut occupied_points = Vec::new();
+    /// Starts a new round, every snake respawning in its team, and the snakes
+    /// of disconnected players being removed.
+    /// Returns the snakes left without room to respawn.
+    pub fn reset_round(&mut self) -> Vec<u8> {
+        let snakes: Vec<_> = self
+            .snakes
+            .drain()
+            .filter(|(_, snake)| snake.frozen_until.is_none())
+            .map(|(snake_id, snake)| (snake_id, snake.team))
+            .collect();
+
+        self.sessions.clear();
 
-        occupied_points.push(self.food);
+        self.reset_arena();
 
-        for snake in self.snakes.values() {
-            for pos in snake.body.iter() {
-                occupied_points.push(*pos);
-            }
+        snakes
+            .into_iter()
+            .filter(|(snake_id, team)| !self.spawn_snake(*snake_id, *team))
+            .map(|(snake_id, _)| snake_id)
+            .collect()
+    }
+
+    /// Switches to another map, starting a new round on it
+    pub fn set_map(&mut self, map: Map, path: &str) -> Vec<u8> {
+        self.map = map;
+        self.map_name = map_name(path);
+        self.reset_round()
+    }
+
+    /// Whether a point is inside the arena and not taken by a wall, a food, a power-up,
+    /// a flag or a snake
+    fn is_free(&self, point: &Point) -> bool {
+        self.bounds.contains(point)
+            && !self.map.is_wall(point)
+            && !self.food.contains(point)
+            && !self.power_ups.contains_key(point)
+            && !self
+                .flags
+                .iter()
+                .any(|flag| flag.carrier.is_none() && flag.position == *point)
+            && !self
+                .snakes
+                .values()
+                .any(|snake| snake.head == *point || snake.body.contains(point))
+    }
+
+    /// The free cell closest to a point, none if the arena is full
+    fn nearest_free_cell(&self, target: Point) -> Option<Point> {
+        self.map
+            .cells()
+            .filter(|point| self.is_free(point))
+            .min_by_key(|point| (point.0 - target.0).abs() + (point.1 - target.1).abs())
+    }
+
+    /// The team a new snake joins: the one it asked for if it exists,
+    /// otherwise the one with the fewest snakes
+    fn pick_team(&self, preferred: u8) -> u8 {
+        let team_count = self.team_scores.len() as u8;
+
+        if team_count == 0 {
+            return 0;
+        }
 
-            occupied_points.push(snake.head);
+        if (1..=team_count).contains(&preferred) {
+            return preferred;
         }
 
-        let mut p1 = Point(self.rng.gen_range(0..WIDTH), self.rng.gen_range(0..HEIGHT));
-        let mut p2 = Point(p1.0 + 1, p1.1);
+        (1..=team_count)
+            .min_by_key(|team| {
+                self.snakes
+                    .values()
+                    .filter(|snake| snake.team == *team)
+                    .count()
+            })
+            .unwrap()
+    }
+
+    /// Spawns a snake in a spawn zone of the map, or anywhere free if the map has
+    /// none or all of them are taken. Returns false if there is no room left.
+    pub fn spawn_snake(&mut self, snake_id: u8, preferred_team: u8) -> bool {
+        let fits = |p1: &Point| self.is_free(p1) && self.is_free(&Point(p1.0 + 1, p1.1));
+
+        let mut candidates: Vec<Point> = self.map.spawns.iter().cloned().filter(fits).collect();
 
-        while occupied_points.contains(&p1) || occupied_points.contains(&p2) {
-            p1 = Point(self.rng.gen_range(0..WIDTH), self.rng.gen_range(0..HEIGHT));
-            p2 = Point(p1.0 + 1, p1.1);
+        if candidates.is_empty() {
+            candidates = self.map.cells().filter(fits).collect();
         }
 
+        let Some(p1) = candidates.choose(&mut self.rng).cloned() else {
+            return false;
+        };
+
         let mut body = VecDeque::new();
-        body.push_back(p2);
+        body.push_back(Point(p1.0 + 1, p1.1));
 
         let snake = Snake {
             head: p1,
             body,
+            team: self.pick_team(preferred_team),
+            score: 0,
+            boosting: false,
+            effects: HashMap::new(),
+            growth: 0,
             direction: Direction::Right,
+            frozen_until: None,
         };
 
         self.snakes.insert(snake_id, snake);
+
+        true
+    }
+
+    /// Freezes the snake of a player that lost its connection, until the player comes
+    /// back with its session token or the grace period ends.
+    /// Returns false if the snake is already dead or reconnecting is disabled.
+    pub fn detach(&mut self, snake_id: u8, token: u64) -> bool {
+        let Some(snake) = self
+            .snakes
+            .get_mut(&snake_id)
+            .filter(|_| !self.reconnect_grace.is_zero())
+        else {
+            return false;
+        };
+
+        snake.frozen_until = Some(Instant::now() + self.reconnect_grace);
+        snake.boosting = false;
+
+        self.sessions.insert(token, snake_id);
+        true
+    }
+
+    /// Gives a frozen snake back to its player, none if the session is unknown or expired
+    pub fn resume(&mut self, token: u64) -> Option<u8> {
+        let snake_id = self.sessions.remove(&token)?;
+        self.snakes.get_mut(&snake_id)?.frozen_until = None;
+
+        Some(snake_id)
+    }
+
+    /// Whether a snake ID is taken, snakes waiting for their player included
+    pub fn is_taken(&self, snake_id: u8) -> bool {
+        self.snakes.contains_key(&snake_id)
+    }
+
+    /// Records the snakes as they are now, reusing the last snapshot if nothing moved since
+    pub fn take_snapshot(&mut self) -> Snapshot {
+        let snakes: BTreeMap<u8, SnakeState> = self
+            .snakes
+            .iter()
+            .map(|(snake_id, snake)| (*snake_id, snake.state()))
+            .collect();
+
+        if let Some(last) = self.snapshots.latest().filter(|last| last.snakes == snakes) {
+            return last.clone();
+        }
+
+        let snapshot = Snapshot {
+            // 0 stands for no snapshot
+            seq: self
+                .snapshots
+                .latest()
+                .map_or(1, |last| last.seq.wrapping_add(1).max(1)),
+            snakes,
+        };
+
+        self.snapshots.push(snapshot.clone());
+        snapshot
     }
 
-    pub fn kill_snake(&mut self, snake_id: u8) {
-        self.snakes.remove(&snake_id);
+    /// Removes a snake, dropping the flag it carried. Returns whether a flag was dropped.
+    pub fn kill_snake(&mut self, snake_id: u8) -> bool {
+        let Some(snake) = self.snakes.remove(&snake_id) else {
+            return false;
+        };
+
+        self.sessions.retain(|_, id| *id != snake_id);
+
+        let Some(flag) = self
+            .flags
+            .iter_mut()
+            .find(|flag| flag.carrier == Some(snake_id))
+        else {
+            return false;
+        };
+
+        // The head may have ended up in a wall or outside the arena
+        let position = if self.bounds.contains(&snake.head) && !self.map.is_wall(&snake.head) {
+            snake.head
+        } else {
+            *snake.body.back().unwrap()
+        };
+
+        flag.carrier = None;
+        flag.position = position;
+
+        true
     }
 
-    pub fn update(&mut self) -> (Option<u8>, Option<Vec<u8>>) {
+    pub fn update(&mut self) -> TickEvents {
+        let mut events = TickEvents::default();
+
         if self.snakes.is_empty() {
-            return (None, None);
+            return events;
         }
 
-        self.snakes.values_mut().for_each(Snake::update_head);
+        if self.mode == GameMode::BattleRoyale {
+            self.update_bounds(&mut events);
+        }
 
-        let mut snake_eat = None;
+        self.update_effects(&mut events);
 
-        for (snake_id, snake) in self.snakes.iter_mut() {
-            if snake.head == self.food {
-                snake_eat = Some(*snake_id);
+        // Players that did not come back in time lose their snake
+        let now = Instant::now();
+        let expired: Vec<u8> = self
+            .snakes
+            .iter()
+            .filter(|(_, snake)| snake.frozen_until.is_some_and(|until| until <= now))
+            .map(|(snake_id, _)| *snake_id)
+            .collect();
+
+        for snake_id in expired {
+            events.flags_changed |= self.kill_snake(snake_id);
+            events.killed.push(snake_id);
+        }
+
+        let speeds: Vec<(u8, u8)> = self
+            .snakes
+            .iter()
+            .map(|(snake_id, snake)| (*snake_id, snake.speed()))
+            .collect();
+
+        // Faster snakes move a cell per step, so nobody jumps over another snake
+        for step in 0..MAX_SPEED {
+            let movers: Vec<u8> = speeds
+                .iter()
+                .filter(|(_, speed)| *speed > step)
+                .map(|(snake_id, _)| *snake_id)
+                .collect();
+
+            self.step(&movers, &mut events);
+        }
+
+        // Boosting costs a tail segment per tick
+        for (snake_id, speed) in speeds {
+            if let Some(snake) = self.snakes.get_mut(&snake_id).filter(|_| speed > 1) {
+                snake.body.pop_front();
+            }
+        }
+
+        // Keep at least one food around, corpse pellets included
+        if self.food.is_empty() {
+            if let Some(food) = self.spawn_food() {
+                events.spawned_food.push(food);
+            }
+        }
+
+        events
+    }
+
+    /// Moves the given snakes a single cell forward
+    fn step(&mut self, movers: &[u8], events: &mut TickEvents) {
+        for snake_id in movers {
+            // It may have died during a previous step
+            let Some(snake) = self.snakes.get_mut(snake_id) else {
+                continue;
+            };
+
+            snake.update_head(&self.map);
+
+            let head = snake.head;
+            let mut eaten = Vec::new();
+
+            if self.food.remove(&head) {
+                eaten.push(head);
+            }
+
+            if snake.has_effect(PowerUp::Magnet) {
+                self.food.retain(|food| {
+                    let pulled = is_near(&head, food, MAGNET_RADIUS);
+
+                    if pulled {
+                        eaten.push(*food);
+                    }
+
+                    !pulled
+                });
+            }
+
+            for food in eaten {
+                events.eaten.push((*snake_id, food));
+                snake.score = snake.score.saturating_add(1);
+                snake.growth = snake.growth.saturating_add(1);
+
+                // Capture the flag teams only score with captures
+                if self.mode == GameMode::Teams {
+                    let team_score = &mut self.team_scores[snake.team as usize - 1];
+                    *team_score = team_score.saturating_add(1);
+                }
+            }
+
+            if snake.growth > 0 {
+                snake.growth -= 1;
             } else {
                 snake.body.pop_front();
             }
+
+            if let Some(power_up) = self.power_ups.remove(&head) {
+                let duration = effect_duration(power_up);
+
+                snake.effects.insert(power_up, Instant::now() + duration);
+                events.effects_started.push((*snake_id, power_up, duration));
+                events.power_ups_changed = true;
+            }
         }
 
-        let mut points = Vec::new();
-        let mut killed_snakes = Vec::new();
+        let mut killed = Vec::new();
+        let mut shielded = Vec::new();
+
+        // Snakes left in the closed zone when the arena shrinks die too
+        for (snake_id, snake) in self.snakes.iter() {
+            if !self.bounds.contains(&snake.head)
+                || snake.body.iter().any(|cell| !self.bounds.contains(cell))
+                || self.map.is_wall(&snake.head)
+            {
+                killed.push(*snake_id);
+            } else if self.collides(*snake_id, snake) {
+                if snake.has_effect(PowerUp::Shield) {
+                    shielded.push(*snake_id);
+                } else {
+                    killed.push(*snake_id);
+                }
+            }
+        }
 
-        for snake in self.snakes.values() {
-            points.extend(snake.body.iter().cloned());
-            points.push(snake.head);
+        for snake_id in shielded {
+            self.snakes
+                .get_mut(&snake_id)
+                .unwrap()
+                .effects
+                .remove(&PowerUp::Shield);
+            events.effects_ended.push((snake_id, PowerUp::Shield));
         }
 
-        for (snake_id, snake) in self.snakes.iter_mut() {
-            if points.iter().filter(|p| **p == snake.head).count() > 1 {
-                killed_snakes.push(*snake_id);
+        // Dead snakes are removed right away, so they stop moving
+        // until their client gets disconnected
+        let mut corpses = Vec::new();
+
+        for snake_id in killed.iter() {
+            corpses.extend(self.snakes.get(snake_id).map(|snake| {
+                let mut cells: Vec<Point> = snake.body.iter().cloned().collect();
+                cells.push(snake.head);
+                cells
+            }));
+
+            events.flags_changed |= self.kill_snake(*snake_id);
+        }
+
+        events.killed.extend(killed);
+
+        if self.corpse_food {
+            for point in corpses.iter().flatten() {
+                if self.is_free(point) {
+                    self.food.insert(*point);
+                    events.spawned_food.push(*point);
+                }
             }
         }
 
-        if snake_eat.is_some() {
-            self.spawn_food();
+        if self.mode == GameMode::CaptureTheFlag {
+            self.update_flags(events);
+        }
+    }
+
+    /// Whether the head of a snake hit itself or another snake. Teammates go
+    /// through each other unless friendly fire is on, and so do ghosts.
+    fn collides(&self, snake_id: u8, snake: &Snake) -> bool {
+        if snake.has_effect(PowerUp::Invincibility) || snake.has_effect(PowerUp::Ghost) {
+            return false;
         }
 
-        if killed_snakes.is_empty() {
-            return (snake_eat, None);
+        if snake.body.contains(&snake.head) {
+            return true;
         }
 
-        (snake_eat, Some(killed_snakes))
+        self.snakes
+            .iter()
+            .filter(|(id, other)| {
+                **id != snake_id
+                    && !other.has_effect(PowerUp::Ghost)
+                    && (self.friendly_fire || snake.team == 0 || other.team != snake.team)
+            })
+            .any(|(_, other)| other.head == snake.head || other.body.contains(&snake.head))
+    }
+
+    /// Picks up flags touched by enemy heads, returns dropped flags touched by their team
+    /// and scores the enemy flags brought to a base
+    fn update_flags(&mut self, events: &mut TickEvents) {
+        let bases: Vec<(u8, Point)> = self
+            .flags
+            .iter()
+            .map(|flag| (flag.team, flag.base))
+            .collect();
+
+        for flag in self.flags.iter_mut() {
+            if let Some(carrier) = flag.carrier {
+                let snake = &self.snakes[&carrier];
+                let in_own_base = bases.iter().any(|(team, base)| {
+                    *team == snake.team && is_near(base, &snake.head, BASE_RADIUS)
+                });
+
+                if in_own_base {
+                    flag.return_home();
+
+                    let team_score = &mut self.team_scores[snake.team as usize - 1];
+                    *team_score = team_score.saturating_add(1);
+                    events.captures.push(carrier);
+                    events.flags_changed = true;
+                }
+
+                continue;
+            }
+
+            let Some((snake_id, snake)) = self
+                .snakes
+                .iter()
+                .find(|(_, snake)| snake.head == flag.position)
+            else {
+                continue;
+            };
+
+            if snake.team != flag.team {
+                flag.carrier = Some(*snake_id);
+                events.flags_changed = true;
+            } else if flag.position != flag.base {
+                flag.return_home();
+                events.flags_changed = true;
+            }
+        }
     }
 
-    fn spawn_food(&mut self) {
-        let x = self.rng.gen_range(0..WIDTH);
-        let y = self.rng.gen_range(0..HEIGHT);
+    /// Ends the expired effects and brings in a new power-up every interval
+    fn update_effects(&mut self, events: &mut TickEvents) {
+        let now = Instant::now();
+
+        for (snake_id, snake) in self.snakes.iter_mut() {
+            snake.effects.retain(|power_up, end| {
+                let active = *end > now;
 
-        let new_food = Point(x, y);
+                if !active {
+                    events.effects_ended.push((*snake_id, *power_up));
+                }
 
-        if self
-            .snakes
-            .values()
-            .any(|snake| snake.body.contains(&new_food))
+                active
+            });
+        }
+
+        if !self.power_ups_enabled
+            || self.power_ups.len() >= MAX_POWER_UPS
+            || self.last_power_up.elapsed() < POWER_UP_INTERVAL
         {
-            self.spawn_food();
             return;
         }
 
-        self.food = new_food;
+        self.last_power_up = now;
+
+        let power_up = *PowerUp::ALL.choose(&mut self.rng).unwrap();
+
+        if let Some(point) = self.random_free_cell() {
+            self.power_ups.insert(point, power_up);
+            events.power_ups_changed = true;
+        }
+    }
+
+    /// Closes the outer ring of the arena every shrink interval, while at least two
+    /// snakes are fighting. The arena opens up again once there is a single survivor.
+    fn update_bounds(&mut self, events: &mut TickEvents) {
+        if self.snakes.len() < 2 {
+            self.last_shrink = Instant::now();
+
+            if self.bounds != self.map.bounds() {
+                self.bounds = self.map.bounds();
+                events.bounds = Some(self.bounds);
+            }
+
+            return;
+        }
+
+        if self.last_shrink.elapsed() < self.shrink_interval {
+            return;
+        }
+
+        self.last_shrink = Instant::now();
+
+        if !self.bounds.shrink(MIN_ARENA_SIZE) {
+            return;
+        }
+
+        // The clients drop the food outside the new bounds by themselves
+        let bounds = self.bounds;
+        self.food.retain(|food| bounds.contains(food));
+
+        // Power-ups left in the closed zone would never be picked up, nor replaced
+        let power_ups = self.power_ups.len();
+        self.power_ups.retain(|point, _| bounds.contains(point));
+        events.power_ups_changed |= self.power_ups.len() != power_ups;
+
+        for flag in self.flags.iter_mut() {
+            if flag.carrier.is_none() && !bounds.contains(&flag.position) {
+                flag.return_home();
+                events.flags_changed = true;
+            }
+        }
+
+        events.bounds = Some(bounds);
+    }
+
+    fn random_free_cell(&mut self) -> Option<Point> {
+        // Random guesses are way cheaper than listing every free cell,
+        // which is only worth it when the arena is crowded
+        for _ in 0..64 {
+            let point = Point(
+                self.rng.gen_range(0..self.map.width),
+                self.rng.gen_range(0..self.map.height),
+            );
+
+            if self.is_free(&point) {
+                return Some(point);
+            }
+        }
+
+        let candidates: Vec<Point> = self
+            .map
+            .cells()
+            .filter(|point| self.is_free(point))
+            .collect();
+
+        candidates.choose(&mut self.rng).cloned()
+    }
+
+    fn spawn_food(&mut self) -> Option<Point> {
+        let new_food = self.random_free_cell()?;
+        self.food.insert(new_food);
+
+        Some(new_food)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A context with a fixed seed, so that the food and spawns are the same on every run
+    fn seeded_context(config: Config) -> GameContext {
+        let mut context = GameContext::new(&config, Map::default());
+        context.rng = StdRng::seed_from_u64(42);
+        context.reset_arena();
+        context
+    }
+
+    /// Puts a snake on the given cells, from its tail to its head
+    fn place_snake(
+        context: &mut GameContext,
+        snake_id: u8,
+        team: u8,
+        cells: &[(i32, i32)],
+        direction: Direction,
+    ) {
+        let mut body: VecDeque<Point> = cells.iter().map(|(x, y)| Point(*x, *y)).collect();
+        let head = body.pop_back().unwrap();
+
+        context.snakes.insert(
+            snake_id,
+            Snake {
+                body,
+                head,
+                team,
+                score: 0,
+                boosting: false,
+                effects: HashMap::new(),
+                growth: 0,
+                direction,
+                frozen_until: None,
+            },
+        );
+    }
+
+    #[test]
+    fn corpse_pellets_land_on_free_cells() {
+        let mut context = seeded_context(Config {
+            corpse_food: true,
+            ..Default::default()
+        });
+        context.food.clear();
+        context.food.insert(Point(30, 30));
+
+        // Snake 1 runs into the body of snake 2, which stays where its head died
+        place_snake(
+            &mut context,
+            1,
+            0,
+            &[(5, 5), (6, 5), (7, 5)],
+            Direction::Right,
+        );
+        place_snake(
+            &mut context,
+            2,
+            0,
+            &[(8, 3), (8, 4), (8, 5), (8, 6)],
+            Direction::Down,
+        );
+
+        let events = context.update();
+
+        assert_eq!(events.killed, [1]);
+        assert!(context.food.contains(&Point(6, 5)));
+        assert!(context.food.contains(&Point(7, 5)));
+        assert!(!context.food.contains(&Point(8, 5)));
+
+        let snake = &context.snakes[&2];
+        assert!(context
+            .food
+            .iter()
+            .all(|food| *food != snake.head && !snake.body.contains(food)));
+    }
+
+    #[test]
+    fn shrinking_removes_what_lies_on_the_closed_ring() {
+        let mut context = seeded_context(Config {
+            mode: GameMode::BattleRoyale,
+            shrink_interval: Duration::ZERO,
+            ..Default::default()
+        });
+        assert!(context.spawn_snake(1, 0));
+        assert!(context.spawn_snake(2, 0));
+
+        context.power_ups.insert(Point(0, 5), PowerUp::Ghost);
+        context.power_ups.insert(Point(10, 10), PowerUp::Shield);
+
+        let mut flag = Flag::new(1, Point(10, 10));
+        flag.position = Point(5, 0);
+        context.flags.push(flag);
+
+        let mut events = TickEvents::default();
+        context.update_bounds(&mut events);
+
+        assert!(events.bounds.is_some());
+        assert!(events.power_ups_changed);
+        assert!(events.flags_changed);
+        assert_eq!(context.power_ups.len(), 1);
+        assert!(context.power_ups.contains_key(&Point(10, 10)));
+        assert!(context.flags[0].position == Point(10, 10));
+    }
+
+    #[test]
+    fn teammates_go_through_each_other() {
+        // Snake 1 runs into the body of snake 2, on a team or not
+        let collide = |friendly_fire: bool, other_team: u8| {
+            let mut context = seeded_context(Config {
+                mode: GameMode::Teams,
+                friendly_fire,
+                ..Default::default()
+            });
+
+            place_snake(
+                &mut context,
+                1,
+                1,
+                &[(5, 5), (6, 5), (7, 5)],
+                Direction::Right,
+            );
+            place_snake(
+                &mut context,
+                2,
+                other_team,
+                &[(8, 3), (8, 4), (8, 5), (8, 6)],
+                Direction::Down,
+            );
+
+            context.update().killed
+        };
+
+        assert!(collide(false, 1).is_empty());
+        assert_eq!(collide(true, 1), [1]);
+        assert_eq!(collide(false, 2), [1]);
+    }
+
+    #[test]
+    fn bringing_the_enemy_flag_home_scores() {
+        let mut context = seeded_context(Config {
+            mode: GameMode::CaptureTheFlag,
+            ..Default::default()
+        });
+        let (home, enemy_base) = (context.flags[0].base, context.flags[1].base);
+
+        // Snake 1 carries the flag of team 2, a cell away from its base
+        let Point(x, y) = home;
+        place_snake(
+            &mut context,
+            1,
+            1,
+            &[(x - 3, y), (x - 2, y)],
+            Direction::Right,
+        );
+        context.flags[1].carrier = Some(1);
+
+        let events = context.update();
+
+        assert_eq!(events.captures, [1]);
+        assert!(events.flags_changed);
+        assert_eq!(context.team_scores, [1, 0]);
+        assert_eq!(context.flags[1].carrier, None);
+        assert!(context.flags[1].position == enemy_base);
+    }
+
+    #[test]
+    fn boosting_moves_two_cells_and_sheds_the_tail() {
+        let mut context = seeded_context(Config::default());
+
+        place_snake(
+            &mut context,
+            1,
+            0,
+            &[(5, 5), (6, 5), (7, 5), (8, 5)],
+            Direction::Right,
+        );
+        context.snakes.get_mut(&1).unwrap().boosting = true;
+
+        context.update();
+
+        let snake = &context.snakes[&1];
+        assert!(snake.head == Point(10, 5));
+        assert_eq!(snake.body.len(), 2);
+        assert!(snake.body.iter().eq(&[Point(8, 5), Point(9, 5)]));
     }
 }
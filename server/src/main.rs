@@ -1,266 +1,1217 @@
+mod bans;
+mod chat;
+mod client;
+mod config;
+mod console;
+mod flood;
 mod game;
+mod replay;
+mod shutdown;
 
 use std::{
+    cmp::Reverse,
     collections::HashMap,
-    io::{self, ErrorKind, Read, Write},
+    env,
+    io::{self, ErrorKind, Write},
     net::Shutdown,
-    sync::{Arc, RwLock, RwLockWriteGuard},
+    sync::{atomic::Ordering, Arc, RwLock},
     thread::{self, sleep},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use common::{
-    packet::{PacketBuilder, PacketType, ReadablePacket},
+    discovery::{self, ServerInfo, DISCOVERY_PORT},
+    heartbeat::{self, Clock, PING_INTERVAL},
+    map::{Bounds, Map},
+    packet::{
+        PacketBuilder, PacketType, ReadablePacket, CAP_COMPRESSION, CAP_PACKED_STEPS,
+        CAP_SNAPSHOTS, MAX_NICKNAME_LEN,
+    },
+    power_up::PowerUp,
+    shutdown::ShutdownNotice,
+    snapshot::Snapshot,
+    status::{PlayerStatus, ServerStatus},
     util::Point,
 };
-use game::Snake;
 use mio::{
-    net::{TcpListener, TcpStream},
+    net::{TcpListener, UdpSocket},
     Events, Interest, Poll, Token,
 };
 
-use crate::game::{Direction, GameContext};
+use crate::{
+    bans::{BanList, Bans, BANNED_REASON},
+    chat::{clean_message, Blocklist, ChatFilter},
+    client::{broadcast, snake_client, Client, Clients, ReadError},
+    config::Config,
+    console::Console,
+    flood::{AcceptLimiter, Admission, Counters, TEMPORARY_BAN},
+    game::{Direction, GameContext},
+    replay::REPLAY_CAPABILITIES,
+    shutdown::{save_replay, save_stats, ShutdownListener},
+};
 
 const PORT: u16 = 14300;
-const TICK_INTERVAL: f32 = 0.05;
 const SERVER: Token = Token(0);
+const DISCOVERY: Token = Token(usize::MAX);
+const SHUTDOWN: Token = Token(usize::MAX - 1);
 const MAX_PLAYERS: usize = 8;
+/// Players, spectators and clients that did not join yet
+const MAX_CONNECTIONS: usize = 32;
+/// So that a single host can not fill the server
+const MAX_CONNECTIONS_PER_IP: usize = 4;
+/// Protocol features the server can use, with the clients that support them
+const CAPABILITIES: u8 = CAP_SNAPSHOTS | CAP_PACKED_STEPS | CAP_COMPRESSION;
+/// How long the clients get to receive the shutdown notice before the server exits
+const SHUTDOWN_LINGER: Duration = Duration::from_secs(2);
+
+/// Facts about the running server, for discovery and status queries
+struct ServerDetails {
+    name: String,
+    started: Instant,
+    /// Timestamps the pings sent to clients
+    clock: Clock,
+    counters: Arc<Counters>,
+}
 
-fn setup_gameloop(
-    context: &Arc<RwLock<GameContext>>,
-    clients: &Arc<RwLock<HashMap<Token, TcpStream>>>,
-) {
+/// Pings every client, and disconnects the ones that stopped answering
+fn setup_heartbeat(clients: &Clients, clock: Clock, idle_timeout: Duration) {
+    let clients = Arc::clone(clients);
+
+    thread::spawn(move || loop {
+        sleep(PING_INTERVAL);
+
+        let ping = heartbeat::ping_packet(clock.timestamp());
+        ping_clients(&mut clients.write().unwrap(), &ping, idle_timeout);
+    });
+}
+
+fn ping_clients(clients: &mut HashMap<Token, Client>, ping: &[u8], idle_timeout: Duration) {
+    for (token, client) in clients.iter_mut() {
+        if client.last_seen.elapsed() > idle_timeout {
+            println!("INFO: Client {} ({}) timed out", token.0, client.addr);
+
+            // Not rejected, the player may come back and resume its session.
+            // The disconnection will be handled in client_read
+            let _ = client.stream.shutdown(Shutdown::Both);
+            continue;
+        }
+
+        let _ = client.send(ping);
+    }
+}
+
+fn setup_gameloop(context: &Arc<RwLock<GameContext>>, clients: &Clients) {
     let context = Arc::clone(context);
     let clients = Arc::clone(clients);
 
     thread::spawn(move || loop {
-        {
+        let tick_interval = {
             let mut context = context.write().unwrap();
 
-            let (snake_id, killed_snakes) = context.update();
+            let events = context.update();
 
-            if let Some(killed_snakes) = killed_snakes {
+            if !events.killed.is_empty() {
                 let mut clients = clients.write().unwrap();
 
-                for snake in killed_snakes.iter() {
-                    // The snake kill itself will be handled in client_read
-
-                    let _ = clients
-                        .get_mut(&Token(*snake as usize))
-                        .unwrap()
-                        .shutdown(Shutdown::Both);
+                for snake in events.killed.iter() {
+                    let mut packet = PacketBuilder::with_capacity(PacketType::SnakeDisconnect, 1);
+                    packet.write(*snake);
+                    let packet = packet.build();
+
+                    // The client disconnection will be handled in client_read
+                    if let Some(client) = snake_client(&mut clients, *snake) {
+                        // Tells the player its session is over, so it does not reconnect
+                        let _ = client.send(&packet);
+                        client.close(Shutdown::Both);
+
+                        client.resumable = false;
+                    } else {
+                        println!("INFO: Nobody came back for snake {snake}");
+                        broadcast_recorded(&mut clients, &mut context, &packet);
+                    }
                 }
             }
 
-            if let Some(snake_id) = snake_id {
+            if let Some(bounds) = events.bounds {
                 let mut clients = clients.write().unwrap();
+                let packet = bounds_packet(&bounds);
+                broadcast_recorded(&mut clients, &mut context, &packet);
+            }
 
-                let mut packet = PacketBuilder::with_capacity(PacketType::FoodUpdate, 3);
-                packet.write(snake_id);
-                packet.write(context.food.0 as u8);
-                packet.write(context.food.1 as u8);
-                let packet = packet.build();
+            if !events.eaten.is_empty() {
+                let mut clients = clients.write().unwrap();
 
-                for client in clients.values_mut() {
-                    let _ = client.write_all(&packet);
+                let mut packet =
+                    PacketBuilder::with_capacity(PacketType::FoodUpdate, events.eaten.len() * 3);
+
+                for (snake_id, Point(x, y)) in events.eaten.iter() {
+                    packet.write(*snake_id);
+                    packet.write(*x as u8);
+                    packet.write(*y as u8);
                 }
+
+                let packet = packet.build();
+                broadcast_recorded(&mut clients, &mut context, &packet);
             }
-        }
 
-        {
-            let context = context.read().unwrap();
+            if events.flags_changed {
+                let mut clients = clients.write().unwrap();
+                let packet = flags_packet(&context);
+                broadcast_recorded(&mut clients, &mut context, &packet);
+            }
+
+            if events.scores_changed() {
+                let mut clients = clients.write().unwrap();
+                let packet = scores_packet(&context);
+                broadcast_recorded(&mut clients, &mut context, &packet);
+            }
+
+            if !events.spawned_food.is_empty() {
+                let mut clients = clients.write().unwrap();
 
-            let mut packet = PacketBuilder::new(PacketType::HeadUpdate);
+                let mut packet = PacketBuilder::with_capacity(
+                    PacketType::FoodSpawn,
+                    events.spawned_food.len() * 2,
+                );
 
-            for (id, snake) in context.snakes.iter() {
-                packet.write(*id);
-                packet.write(snake.head.0 as u8);
-                packet.write(snake.head.1 as u8);
+                for Point(x, y) in events.spawned_food.iter() {
+                    packet.write(*x as u8);
+                    packet.write(*y as u8);
+                }
+
+                let packet = packet.build();
+                broadcast_recorded(&mut clients, &mut context, &packet);
             }
 
-            let packet = packet.build();
+            let snapshot = context.take_snapshot();
+            context.replay.record_snapshot(&snapshot);
+
             let mut clients = clients.write().unwrap();
+            send_snapshot(&mut clients, &context, &snapshot);
 
-            for client in clients.values_mut() {
-                let _ = client.write_all(&packet);
+            if events.power_ups_changed {
+                let packet = power_ups_packet(&context);
+                broadcast_recorded(&mut clients, &mut context, &packet);
+            }
 
-                // println!("DEBUG: Sending packet {:?}", packet);
+            if !events.effects_started.is_empty() {
+                let packet = effects_packet(&events.effects_started);
+                broadcast_recorded(&mut clients, &mut context, &packet);
+            }
+
+            if !events.effects_ended.is_empty() {
+                let mut packet = PacketBuilder::with_capacity(
+                    PacketType::EffectEnd,
+                    events.effects_ended.len() * 2,
+                );
+
+                for (snake_id, power_up) in events.effects_ended.iter() {
+                    packet.write(*snake_id);
+                    packet.write(power_up.to_u8());
+                }
+
+                let packet = packet.build();
+                broadcast_recorded(&mut clients, &mut context, &packet);
             }
-        }
 
-        sleep(Duration::from_secs_f32(TICK_INTERVAL));
+            context.tick_interval
+        };
+
+        sleep(tick_interval);
     });
 }
 
+fn bounds_packet(bounds: &Bounds) -> Vec<u8> {
+    let mut packet = PacketBuilder::with_capacity(PacketType::ArenaBounds, 4);
+    packet.write(bounds.min.0 as u8);
+    packet.write(bounds.min.1 as u8);
+    packet.write(bounds.max.0 as u8);
+    packet.write(bounds.max.1 as u8);
+
+    packet.build()
+}
+
+fn scores_packet(context: &GameContext) -> Vec<u8> {
+    let mut packet = PacketBuilder::with_capacity(
+        PacketType::ScoreUpdate,
+        1 + context.team_scores.len() * 2 + context.snakes.len() * 3,
+    );
+    packet.write(context.team_scores.len() as u8);
+
+    for score in context.team_scores.iter() {
+        packet.write_u16_le(*score);
+    }
+
+    for (id, snake) in context.snakes.iter() {
+        packet.write(*id);
+        packet.write_u16_le(snake.score);
+    }
+
+    packet.build()
+}
+
+fn flags_packet(context: &GameContext) -> Vec<u8> {
+    let mut packet = PacketBuilder::with_capacity(PacketType::FlagUpdate, context.flags.len() * 6);
+
+    for flag in context.flags.iter() {
+        packet.write(flag.team);
+        packet.write(flag.base.0 as u8);
+        packet.write(flag.base.1 as u8);
+        packet.write(flag.carrier.unwrap_or(0));
+        packet.write(flag.position.0 as u8);
+        packet.write(flag.position.1 as u8);
+    }
+
+    packet.build()
+}
+
+fn power_ups_packet(context: &GameContext) -> Vec<u8> {
+    let mut packet =
+        PacketBuilder::with_capacity(PacketType::PowerUpUpdate, context.power_ups.len() * 3);
+
+    for (Point(x, y), power_up) in context.power_ups.iter() {
+        packet.write(power_up.to_u8());
+        packet.write(*x as u8);
+        packet.write(*y as u8);
+    }
+
+    packet.build()
+}
+
+fn effects_packet(effects: &[(u8, PowerUp, Duration)]) -> Vec<u8> {
+    let mut packet = PacketBuilder::with_capacity(PacketType::EffectStart, effects.len() * 4);
+
+    for (snake_id, power_up, duration) in effects.iter() {
+        packet.write(*snake_id);
+        packet.write(power_up.to_u8());
+        packet.write_u16_le((duration.as_millis() / 100) as u16);
+    }
+
+    packet.build()
+}
+
 fn disconnect_client(
-    snake_id: u8,
     poll: &mut Poll,
     context: &Arc<RwLock<GameContext>>,
-    clients: &Arc<RwLock<HashMap<Token, TcpStream>>>,
+    clients: &Clients,
     token: Token,
 ) {
     println!("INFO: Client disconnected, token = {}", token.0);
 
-    let mut clients = clients.write().unwrap();
     let mut context = context.write().unwrap();
+    let mut clients = clients.write().unwrap();
 
-    let mut disconnected_stream = clients.remove(&token).unwrap();
-    let _ = poll.registry().deregister(&mut disconnected_stream);
+    let mut disconnected = clients.remove(&token).unwrap();
+    let _ = poll.registry().deregister(&mut disconnected.stream);
+
+    let Some(snake_id) = disconnected.snake_id else {
+        return;
+    };
+
+    // The connection may only have dropped for a moment, the snake waits for its player
+    if let Some(session) = disconnected.session.filter(|_| disconnected.resumable) {
+        if context.detach(snake_id, session) {
+            println!(
+                "INFO: Snake {snake_id} waits {}s for its player to reconnect",
+                context.reconnect_grace.as_secs()
+            );
+            return;
+        }
+    }
 
-    context.kill_snake(token.0 as u8);
+    let flag_dropped = context.kill_snake(snake_id);
 
     let mut packet = PacketBuilder::with_capacity(PacketType::SnakeDisconnect, 1);
     packet.write(snake_id);
+
     let packet = packet.build();
+    broadcast_recorded(&mut clients, &mut context, &packet);
 
-    for client in clients.values_mut() {
-        let _ = client.write_all(&packet);
+    if flag_dropped {
+        let packet = flags_packet(&context);
+        broadcast_recorded(&mut clients, &mut context, &packet);
     }
 }
 
-fn client_read(
-    poll: &mut Poll,
-    context: Arc<RwLock<GameContext>>,
-    clients: Arc<RwLock<HashMap<Token, TcpStream>>>,
+/// Sends a packet about the game to every client that joined, and records it in the replay
+fn broadcast_recorded(
+    clients: &mut HashMap<Token, Client>,
+    context: &mut GameContext,
+    packet: &[u8],
+) {
+    broadcast(clients, packet);
+    context.replay.record(packet);
+}
+
+/// Gives a player the token to reconnect with, and how long it has to
+fn session_packet(token: u64, grace: Duration) -> Vec<u8> {
+    let mut packet = PacketBuilder::with_capacity(PacketType::Session, 10);
+    packet.write_u64_le(token);
+    packet.write_u16_le(grace.as_secs().min(u16::MAX as u64) as u16);
+
+    packet.build()
+}
+
+fn rejected_packet(reason: &str) -> Vec<u8> {
+    let mut packet = PacketBuilder::with_capacity(PacketType::ConnRejected, 1 + reason.len());
+    packet.write_str(reason);
+
+    packet.build()
+}
+
+/// Tells a client why it can not play, the disconnection will be handled in client_read
+fn reject_client(client: &mut Client, reason: &str) {
+    client.resumable = false;
+
+    let _ = client.send(&rejected_packet(reason));
+    client.close(Shutdown::Both);
+}
+
+/// Strips control characters and cuts the nickname to the maximum length,
+/// an empty one is replaced by a generic name
+fn clean_nickname(nickname: &str, token: Token) -> String {
+    let nickname: String = nickname
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_NICKNAME_LEN)
+        .collect();
+    let nickname = nickname.trim();
+
+    if nickname.is_empty() {
+        format!("Player {}", token.0)
+    } else {
+        nickname.to_string()
+    }
+}
+
+/// What a client asked for in its Join packet
+struct JoinRequest {
+    team: u8,
+    spectate: bool,
+    nickname: String,
+    /// Token of the session to resume, after the connection dropped
+    session: Option<u64>,
+    /// Protocol features the client supports
+    capabilities: u8,
+}
+
+fn join_client(
     token: Token,
+    request: JoinRequest,
+    context: &Arc<RwLock<GameContext>>,
+    clients: &Clients,
+    bans: &Bans,
 ) {
-    let clients_map = clients.read().unwrap();
-    let mut client = clients_map.get(&token).unwrap();
+    let mut context = context.write().unwrap();
+    let mut clients = clients.write().unwrap();
 
-    let mut size_bytes = [0u8; 2];
-    let snake_id = token.0 as u8;
+    if clients[&token].joined {
+        eprintln!("WARN: Client {} tried to join twice", token.0);
+        return;
+    }
+
+    let JoinRequest {
+        team: preferred_team,
+        spectate,
+        nickname,
+        session,
+        capabilities,
+    } = request;
+
+    let client = clients.get_mut(&token).unwrap();
+    client.nickname = clean_nickname(&nickname, token);
+    client.capabilities = capabilities & CAPABILITIES;
+
+    // Checked before spawning, as nicknames can be banned too
+    if bans
+        .read()
+        .unwrap()
+        .is_banned(&client.addr.ip(), &client.nickname)
+    {
+        println!(
+            "INFO: Refused banned client {} ({})",
+            client.addr, client.nickname
+        );
+
+        reject_client(client, BANNED_REASON);
+        return;
+    }
+
+    // Clients from before the snapshots would never see the snakes move
+    if capabilities & CAP_SNAPSHOTS == 0 {
+        println!(
+            "INFO: Refused outdated client {} ({})",
+            client.addr, client.nickname
+        );
+
+        reject_client(
+            client,
+            "Your client is too old for this server, please update it",
+        );
+        return;
+    }
+
+    if spectate {
+        let client = clients.get_mut(&token).unwrap();
+
+        // Spectators get the snake ID 0, owned by nobody
+        if send_fullstate(0, client, &mut context).is_ok() {
+            client.joined = true;
 
-    if let Err(err) = client.read_exact(&mut size_bytes) {
-        if err.kind() != ErrorKind::UnexpectedEof {
-            eprintln!("ERROR: Failed to read from client {snake_id}: {err}");
+            println!(
+                "INFO: Client {} ({}) joined as a spectator",
+                client.addr, client.nickname
+            );
         }
 
-        // Unlock clients
-        drop(clients_map);
+        return;
+    }
+
+    if let Some(snake_id) =
+        session.and_then(|session| resume_snake(session, &mut context, &mut clients))
+    {
+        let client = clients.get_mut(&token).unwrap();
+        client.snake_id = Some(snake_id);
+        client.session = session;
+
+        let grace = context.reconnect_grace;
+
+        if send_fullstate(snake_id, client, &mut context).is_ok()
+            && client
+                .send(&session_packet(session.unwrap(), grace))
+                .is_ok()
+        {
+            client.joined = true;
+
+            println!(
+                "INFO: Client {} ({}) got snake {snake_id} back",
+                client.addr, client.nickname
+            );
+        }
 
-        disconnect_client(snake_id, poll, &context, &clients, token);
         return;
     }
 
-    // println!("DEBUG: Message received: {:?}", &buffer[..buff_size]);
+    // Snakes waiting for their player to reconnect keep their ID
+    let taken =
+        |id: u8| context.is_taken(id) || clients.values().any(|client| client.snake_id == Some(id));
+
+    // IDs 0 and 0xff have special meanings in the protocol
+    let players = (1..0xff).filter(|id| taken(*id)).count();
+    let snake_id = (1..0xff).find(|id| !taken(*id));
 
-    let buffer_size = u16::from_le_bytes(size_bytes) as usize;
+    let Some(snake_id) = snake_id.filter(|_| players < MAX_PLAYERS) else {
+        println!("INFO: Server full, rejecting client {}", token.0);
 
-    if buffer_size == 0 {
-        eprintln!("WARN: Empty packet received from {snake_id}");
+        reject_client(clients.get_mut(&token).unwrap(), "The server is full");
         return;
+    };
+
+    if !context.spawn_snake(snake_id, preferred_team) {
+        eprintln!("WARN: No room left to spawn a snake");
+
+        reject_client(
+            clients.get_mut(&token).unwrap(),
+            "No room left in the arena",
+        );
+        return;
+    }
+
+    let team = context.snakes[&snake_id].team;
+
+    let packet = scores_packet(&context);
+    broadcast_recorded(&mut clients, &mut context, &packet);
+
+    let client = clients.get_mut(&token).unwrap();
+    client.snake_id = Some(snake_id);
+
+    if send_fullstate(snake_id, client, &mut context).is_ok() {
+        client.joined = true;
+
+        println!(
+            "INFO: Client {} ({}) joined with snake {snake_id}, team {team}",
+            client.addr, client.nickname
+        );
+    }
+
+    if !context.reconnect_grace.is_zero() {
+        // 0 stands for no session
+        let session = rand::random::<u64>().max(1);
+
+        client.session = Some(session);
+        let _ = client.send(&session_packet(session, context.reconnect_grace));
+    }
+}
+
+/// Finds the snake a reconnecting player left behind, none if it is gone
+fn resume_snake(
+    session: u64,
+    context: &mut GameContext,
+    clients: &mut HashMap<Token, Client>,
+) -> Option<u8> {
+    // The server may not have noticed yet that the old connection is dead
+    if let Some(old) = clients
+        .values_mut()
+        .find(|client| client.session == Some(session))
+    {
+        old.session = None;
+        let _ = old.stream.shutdown(Shutdown::Both);
+
+        return old.snake_id.take().filter(|id| context.is_taken(*id));
+    }
+
+    context.resume(session)
+}
+
+fn status_packet(
+    details: &ServerDetails,
+    context: &GameContext,
+    clients: &HashMap<Token, Client>,
+) -> Vec<u8> {
+    let mut players: Vec<_> = clients
+        .values()
+        .filter_map(|client| {
+            let snake_id = client.snake_id?;
+            let snake = context.snakes.get(&snake_id)?;
+
+            Some(PlayerStatus {
+                nickname: client.nickname.clone(),
+                snake_id,
+                team: snake.team,
+                score: snake.score,
+                length: snake.body.len() as u16 + 1,
+                // The highest value means unknown
+                ping: client
+                    .ping
+                    .map(|ping| ping.as_millis().min(u16::MAX as u128 - 1) as u16),
+            })
+        })
+        .collect();
+
+    players.sort_by_key(|player| Reverse(player.score));
+
+    let spectators = clients
+        .values()
+        .filter(|client| client.joined && client.snake_id.is_none())
+        .count();
+
+    ServerStatus {
+        name: details.name.clone(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        mode: context.mode,
+        map: context.map_name.clone(),
+        width: context.map.width as u8,
+        height: context.map.height as u8,
+        tick_rate: (1.0 / context.tick_interval.as_secs_f32()).round() as u8,
+        uptime: details.started.elapsed().as_secs() as u32,
+        max_players: MAX_PLAYERS as u8,
+        spectators: spectators as u8,
+        players,
     }
+    .to_packet()
+}
 
-    let mut buffer = vec![0; buffer_size];
+/// A chat message, from the server itself if the snake ID is 0 and the sender is `Server`
+fn chat_packet(snake_id: u8, sender: &str, message: &str) -> Vec<u8> {
+    let mut packet =
+        PacketBuilder::with_capacity(PacketType::ChatMessage, 3 + sender.len() + message.len());
+
+    packet.write(snake_id);
+    packet.write_str(sender);
+    packet.write_str(message);
 
-    if let Err(err) = client.read_exact(&mut buffer) {
-        eprintln!("ERROR: Failed to read from TCP Stream (buffer) {snake_id}: {err}");
+    packet.build()
+}
+
+fn client_read(
+    poll: &mut Poll,
+    details: &ServerDetails,
+    chat_filter: &dyn ChatFilter,
+    context: Arc<RwLock<GameContext>>,
+    clients: Clients,
+    bans: &Bans,
+    token: Token,
+) {
+    let mut clients_map = clients.write().unwrap();
+
+    // The client may have been disconnected earlier in the same poll
+    let Some(client) = clients_map.get_mut(&token) else {
         return;
+    };
+
+    let frames = match client.receive(&details.counters) {
+        Ok(frames) => frames,
+        Err(err) => {
+            match err {
+                ReadError::Closed => {}
+                ReadError::Failed(err) => {
+                    eprintln!("ERROR: Failed to read from client {}: {err}", token.0);
+                }
+                ReadError::Flooding => {
+                    eprintln!(
+                        "WARN: Client {} ({}) is flooding, banning it for {} minutes",
+                        token.0,
+                        client.addr,
+                        TEMPORARY_BAN.as_secs() / 60
+                    );
+
+                    reject_client(client, "You are sending too much data");
+                    bans.write()
+                        .unwrap()
+                        .ban_temporarily(client.addr.ip(), TEMPORARY_BAN);
+
+                    let counters = &details.counters;
+                    counters.flood_kicks.fetch_add(1, Ordering::Relaxed);
+                    counters.temporary_bans.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+
+            // Unlock clients
+            drop(clients_map);
+
+            disconnect_client(poll, &context, &clients, token);
+            return;
+        }
+    };
+
+    // Unlock clients
+    drop(clients_map);
+
+    for frame in frames.iter() {
+        handle_packet(details, chat_filter, &context, &clients, bans, token, frame);
     }
+}
 
-    let mut packet = ReadablePacket::from_bytes(&buffer);
+fn handle_packet(
+    details: &ServerDetails,
+    chat_filter: &dyn ChatFilter,
+    context: &Arc<RwLock<GameContext>>,
+    clients: &Clients,
+    bans: &Bans,
+    token: Token,
+    frame: &[u8],
+) {
+    let client_id = token.0;
+
+    let Some(mut packet) = ReadablePacket::try_from_bytes(frame) else {
+        eprintln!("WARN: Invalid packet received from {client_id}");
+        return;
+    };
+
+    // A Join packet earlier in the same read may have given the client a snake
+    let Some(snake_id) = clients
+        .read()
+        .unwrap()
+        .get(&token)
+        .map(|client| client.snake_id)
+    else {
+        return;
+    };
 
     match packet.r#type {
+        PacketType::Join => {
+            let (Some(team), Some(spectate)) = (packet.try_read(), packet.try_read()) else {
+                return;
+            };
+
+            let request = JoinRequest {
+                team,
+                spectate: spectate != 0,
+                // Older clients don't send a nickname, a session to resume nor capabilities
+                nickname: packet.try_read_str().unwrap_or_default(),
+                session: packet.try_read_u64_le().filter(|session| *session != 0),
+                capabilities: packet.try_read().unwrap_or(0),
+            };
+
+            join_client(token, request, context, clients, bans);
+        }
         PacketType::DirectionUpdate => {
-            let direction = match packet.read() {
-                0x1 => Some(Direction::Up),
-                0x2 => Some(Direction::Down),
-                0x3 => Some(Direction::Left),
-                0x4 => Some(Direction::Right),
+            let direction = match packet.try_read() {
+                Some(0x1) => Some(Direction::Up),
+                Some(0x2) => Some(Direction::Down),
+                Some(0x3) => Some(Direction::Left),
+                Some(0x4) => Some(Direction::Right),
                 _ => None,
             };
             if let Some(direction) = direction {
                 let mut context = context.write().unwrap();
 
-                context
-                    .snakes
-                    .get_mut(&snake_id)
-                    .unwrap()
-                    .change_direction(direction);
+                // The snake may already be dead, waiting for the disconnection
+                if let Some(snake) = snake_id.and_then(|id| context.snakes.get_mut(&id)) {
+                    snake.change_direction(direction);
+                }
+            }
+        }
+        PacketType::Boost => {
+            let Some(boosting) = packet.try_read() else {
+                return;
+            };
+
+            let mut context = context.write().unwrap();
+
+            if let Some(snake) = snake_id.and_then(|id| context.snakes.get_mut(&id)) {
+                snake.boosting = boosting != 0;
+            }
+        }
+        PacketType::ChatSend => {
+            let Some(message) = packet.try_read_str().as_deref().and_then(clean_message) else {
+                return;
+            };
+
+            let mut clients = clients.write().unwrap();
+            let client = clients.get_mut(&token).unwrap();
+
+            if !client.joined {
+                return;
+            }
+
+            if !client.chat.allow() {
+                let warning = "You are sending messages too fast";
+                let _ = client.send(&chat_packet(0, "Server", warning));
+                return;
+            }
+
+            let Some(message) = chat_filter.filter(&message) else {
+                return;
+            };
+
+            let nickname = client.nickname.clone();
+            println!("INFO: [chat] {nickname}: {message}");
+
+            broadcast(
+                &mut clients,
+                &chat_packet(snake_id.unwrap_or(0), &nickname, &message),
+            );
+        }
+        PacketType::Ping => {
+            let Some(timestamp) = heartbeat::read_timestamp(&mut packet) else {
+                return;
+            };
+
+            let mut clients = clients.write().unwrap();
+
+            if let Some(client) = clients.get_mut(&token) {
+                let _ = client.send(&heartbeat::pong_packet(timestamp));
+            }
+        }
+        PacketType::Pong => {
+            let Some(timestamp) = heartbeat::read_timestamp(&mut packet) else {
+                return;
+            };
+
+            let mut clients = clients.write().unwrap();
+
+            if let Some(client) = clients.get_mut(&token) {
+                client.ping = Some(details.clock.since(timestamp));
+            }
+        }
+        PacketType::SnapshotAck => {
+            let Some(seq) = packet.try_read_u32_le() else {
+                return;
+            };
+
+            let mut clients = clients.write().unwrap();
+
+            // Acknowledging 0 asks for a keyframe
+            if let Some(client) = clients.get_mut(&token) {
+                client.acked = Some(seq).filter(|seq| *seq != 0);
+            }
+        }
+        PacketType::StatusRequest => {
+            let context = context.read().unwrap();
+            let mut clients = clients.write().unwrap();
+
+            let status = status_packet(details, &context, &clients);
+            let client = clients.get_mut(&token).unwrap();
+
+            let _ = client.send(&status);
+
+            // A status query takes no part in the game, the connection is over
+            if !client.joined {
+                client.close(Shutdown::Both);
             }
         }
         _ => {
-            eprintln!("WARN: Invalid packet type received from {snake_id}");
+            eprintln!("WARN: Invalid packet type received from {client_id}");
         }
     };
 }
 
-fn send_fullstate(
-    snake_id: u8,
-    stream: &mut TcpStream,
-    context: &Arc<RwLock<GameContext>>,
-) -> io::Result<()> {
-    let context = Arc::clone(context);
-    let context = context.read().unwrap();
+/// Sends the whole game state
+fn send_fullstate(snake_id: u8, client: &mut Client, context: &mut GameContext) -> io::Result<()> {
+    let state = fullstate(snake_id, client.capabilities, context);
 
-    let mut packet = PacketBuilder::new(PacketType::Info);
-    packet.write(snake_id);
+    client.send(&state)
+}
 
-    for (id, snake) in context.snakes.iter() {
-        packet.write(*id);
-        packet.write_u16_le(snake.body.len() as u16 + 1);
+/// The whole game state as seen by a snake, the large packets being compressed
+/// and fragmented if the capabilities allow it
+fn fullstate(snake_id: u8, capabilities: u8, context: &mut GameContext) -> Vec<u8> {
+    let mut state = Vec::new();
 
-        for Point(x, y) in snake.body.iter() {
-            packet.write(*x as u8);
-            packet.write(*y as u8);
-        }
+    let map = &context.map;
+    let mut packet = PacketBuilder::with_capacity(PacketType::MapData, 5 + map.walls.len() * 2);
+    packet.write(map.width as u8);
+    packet.write(map.height as u8);
+    packet.write(map.edges.to_u8());
+    packet.write_u16_le(map.walls.len() as u16);
 
-        packet.write(snake.head.0 as u8);
-        packet.write(snake.head.1 as u8);
+    for Point(x, y) in map.walls.iter() {
+        packet.write(*x as u8);
+        packet.write(*y as u8);
     }
 
-    packet.write(0xff);
-    packet.write(context.food.0 as u8);
-    packet.write(context.food.1 as u8);
+    state.extend_from_slice(&packet.build_for(capabilities));
+    state.extend_from_slice(&bounds_packet(&context.bounds));
 
-    drop(context);
+    let mut packet = PacketBuilder::with_capacity(PacketType::Info, 3 + context.food.len() * 2);
+    packet.write(snake_id);
+    packet.write_u16_le(context.food.len() as u16);
 
-    let packet = packet.build();
+    for Point(x, y) in context.food.iter() {
+        packet.write(*x as u8);
+        packet.write(*y as u8);
+    }
+
+    let packet = packet.build_for(capabilities);
 
     // println!("DEBUG: Sending initial packet: {:?}", packet);
 
-    stream.write_all(&packet)?;
+    state.extend_from_slice(&packet);
+    state.extend_from_slice(
+        &context
+            .take_snapshot()
+            .keyframe()
+            .to_packet_for(capabilities),
+    );
+    state.extend_from_slice(&scores_packet(context));
+
+    if !context.flags.is_empty() {
+        state.extend_from_slice(&flags_packet(context));
+    }
 
-    Ok(())
+    state.extend_from_slice(&power_ups_packet(context));
+
+    let now = Instant::now();
+    let effects: Vec<(u8, PowerUp, Duration)> = context
+        .snakes
+        .iter()
+        .flat_map(|(snake_id, snake)| {
+            snake.effects.iter().map(move |(power_up, end)| {
+                (*snake_id, *power_up, end.saturating_duration_since(now))
+            })
+        })
+        .collect();
+
+    if !effects.is_empty() {
+        state.extend_from_slice(&effects_packet(&effects));
+    }
+
+    state
+}
+
+/// Starts the replay of a round over from the whole game state, as a spectator gets it
+fn record_fullstate(context: &mut GameContext) {
+    let state = fullstate(0, REPLAY_CAPABILITIES, context);
+
+    context.replay.record_state(&state);
 }
 
-fn broadcast_snake(
-    clients: &mut RwLockWriteGuard<HashMap<Token, TcpStream>>,
-    snake_id: u8,
-    snake: &Snake,
+/// Sends every client what changed since the last snapshot it acknowledged
+fn send_snapshot(clients: &mut HashMap<Token, Client>, context: &GameContext, snapshot: &Snapshot) {
+    // Clients that acknowledged the same snapshot and read the same encoding get the same packet
+    let mut packets: HashMap<(Option<u32>, u8), Vec<u8>> = HashMap::new();
+
+    for client in clients.values_mut().filter(|client| client.joined) {
+        let base = client.acked.and_then(|seq| context.snapshots.get(seq));
+        let packed_steps = client.capabilities & CAP_PACKED_STEPS != 0;
+
+        let packet = packets
+            .entry((base.map(|base| base.seq), client.capabilities))
+            .or_insert_with(|| {
+                match base {
+                    Some(base) => snapshot.delta(base, packed_steps),
+                    None => snapshot.keyframe(),
+                }
+                .to_packet_for(client.capabilities)
+            });
+
+        let _ = client.send(packet);
+    }
+}
+
+/// Answers the LAN discovery probes received so far
+fn answer_discovery(
+    socket: &UdpSocket,
+    details: &ServerDetails,
+    context: &Arc<RwLock<GameContext>>,
+    clients: &Clients,
 ) {
-    let mut packet = PacketBuilder::with_capacity(PacketType::SnakeConnect, 5);
-    packet.write(snake_id);
-    packet.write_u16_le(snake.body.len() as u16 + 1);
+    let mut buffer = [0u8; 64];
 
-    for Point(x, y) in snake.body.iter() {
-        packet.write(*x as u8);
-        packet.write(*y as u8);
+    loop {
+        let (size, addr) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(err) if err.kind() == ErrorKind::WouldBlock => return,
+            Err(err) => {
+                eprintln!("WARN: Could not read a discovery probe: {err}");
+                return;
+            }
+        };
+
+        if buffer[..size] != discovery::probe_packet() {
+            continue;
+        }
+
+        let players = clients
+            .read()
+            .unwrap()
+            .values()
+            .filter(|client| client.snake_id.is_some())
+            .count();
+
+        let info = ServerInfo {
+            name: details.name.clone(),
+            players: players as u8,
+            max_players: MAX_PLAYERS as u8,
+            mode: context.read().unwrap().mode,
+            port: PORT,
+        };
+
+        if let Err(err) = socket.send_to(&info.to_packet(), addr) {
+            eprintln!("WARN: Could not answer the discovery probe of {addr}: {err}");
+        }
     }
+}
+
+/// Accepts the pending connections, refusing banned addresses and the ones
+/// connecting too fast or too many times
+fn accept_clients(
+    listener: &TcpListener,
+    poll: &mut Poll,
+    next_token: &mut usize,
+    clients: &Clients,
+    bans: &Bans,
+    accept_limiter: &mut AcceptLimiter,
+    counters: &Counters,
+) {
+    // The listener only signals new connections once, it has to be drained
+    loop {
+        let (mut stream, client_addr) = match listener.accept() {
+            Ok(accepted) => accepted,
+            Err(err) if err.kind() == ErrorKind::WouldBlock => return,
+            Err(err) => {
+                eprintln!("Accept call failed: {err}");
+                return;
+            }
+        };
 
-    packet.write(snake.head.0 as u8);
-    packet.write(snake.head.1 as u8);
+        // The nickname is only known once the client joins
+        if bans.read().unwrap().is_banned(&client_addr.ip(), "") {
+            println!("INFO: Refused banned client {client_addr}");
 
-    let packet = packet.build();
+            let _ = stream.write_all(&rejected_packet(BANNED_REASON));
+            continue;
+        }
 
-    for (id, client) in clients.iter_mut() {
-        if id.0 as u8 == snake_id {
+        match accept_limiter.admit(client_addr.ip()) {
+            Admission::Accept => {}
+            Admission::Refuse => {
+                counters.connections_refused.fetch_add(1, Ordering::Relaxed);
+
+                let _ = stream.write_all(&rejected_packet(
+                    "Too many connection attempts, try again later",
+                ));
+                continue;
+            }
+            Admission::Ban => {
+                eprintln!(
+                    "WARN: {} keeps connecting too fast, banning it for {} minutes",
+                    client_addr.ip(),
+                    TEMPORARY_BAN.as_secs() / 60
+                );
+
+                bans.write()
+                    .unwrap()
+                    .ban_temporarily(client_addr.ip(), TEMPORARY_BAN);
+
+                counters.connections_refused.fetch_add(1, Ordering::Relaxed);
+                counters.temporary_bans.fetch_add(1, Ordering::Relaxed);
+
+                let _ = stream.write_all(&rejected_packet(BANNED_REASON));
+                continue;
+            }
+        }
+
+        let mut clients = clients.write().unwrap();
+
+        if clients.len() >= MAX_CONNECTIONS {
+            let _ = stream.write_all(&rejected_packet("The server is full"));
+            continue;
+        }
+
+        let same_host = clients
+            .values()
+            .filter(|client| client.addr.ip() == client_addr.ip())
+            .count();
+
+        if same_host >= MAX_CONNECTIONS_PER_IP {
+            println!("INFO: Too many connections from {}", client_addr.ip());
+
+            let _ = stream.write_all(&rejected_packet("Too many connections from your address"));
             continue;
         }
 
-        // println!("DEBUG: Sending packet {:?}", packet);
-        let _ = client.write_all(&packet);
+        // Token 0 is the listener, and the last two the discovery socket and the shutdown signal
+        while Token(*next_token) == SERVER
+            || Token(*next_token) == DISCOVERY
+            || Token(*next_token) == SHUTDOWN
+            || clients.contains_key(&Token(*next_token))
+        {
+            *next_token = next_token.wrapping_add(1);
+        }
+
+        let token = Token(*next_token);
+        *next_token = next_token.wrapping_add(1);
+
+        match poll
+            .registry()
+            .register(&mut stream, token, Interest::READABLE | Interest::WRITABLE)
+        {
+            Ok(_) => {
+                // The client gets a snake, or becomes a spectator,
+                // once it sends its Join packet
+                clients.insert(token, Client::new(stream, client_addr));
+                counters
+                    .connections_accepted
+                    .fetch_add(1, Ordering::Relaxed);
+
+                println!(
+                    "INFO: Client {client_addr} connected, with token {}!",
+                    token.0
+                );
+            }
+            Err(err) => eprintln!("Could not register stream {err}"),
+        }
+    }
+}
+
+/// Stops accepting clients and tells the connected ones why the server closes,
+/// giving them a moment to receive it
+fn shut_down(
+    poll: &mut Poll,
+    mut listener: TcpListener,
+    details: &ServerDetails,
+    clients: &Clients,
+    notice: &ShutdownNotice,
+) {
+    println!("INFO: Shutting down: {}", notice.reason);
+
+    let _ = poll.registry().deregister(&mut listener);
+    drop(listener);
+
+    let packet = notice.to_packet();
+
+    // Whatever is still queued goes out first, the notice being the last packet
+    for client in clients.write().unwrap().values_mut() {
+        client.resumable = false;
+
+        let _ = client.send(&packet);
+        client.close(Shutdown::Write);
+    }
+
+    // Closing sockets with unread data would reset the connections, dropping the notice
+    let deadline = Instant::now() + SHUTDOWN_LINGER;
+    let mut events = Events::with_capacity(128);
+
+    while !clients.read().unwrap().is_empty() && Instant::now() < deadline {
+        if poll
+            .poll(&mut events, Some(deadline - Instant::now()))
+            .is_err()
+        {
+            break;
+        }
+
+        let mut clients = clients.write().unwrap();
+
+        for event in events.iter() {
+            let token = event.token();
+
+            let Some(client) = clients.get_mut(&token) else {
+                continue;
+            };
+
+            if event.is_writable() {
+                let _ = client.flush();
+            }
+
+            if client.receive(&details.counters).is_err() {
+                let mut closed = clients.remove(&token).unwrap();
+                let _ = poll.registry().deregister(&mut closed.stream);
+            }
+        }
+    }
+
+    let unsent = clients
+        .read()
+        .unwrap()
+        .values()
+        .filter(|client| !client.is_flushed())
+        .count();
+
+    if unsent > 0 {
+        eprintln!("WARN: {unsent} clients did not get everything before the server stopped");
     }
 }
 
 fn main() -> io::Result<()> {
-    let context = Arc::new(RwLock::new(GameContext::new()));
+    let config = Config::from_args(env::args().skip(1))
+        .map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?;
+
+    let mut map = match &config.map {
+        Some(path) => {
+            Map::load(path).map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?
+        }
+        None => Map::default(),
+    };
+
+    if let Some(edges) = config.edges {
+        map.edges = edges;
+    }
+
+    println!(
+        "INFO: Playing {} on a {}x{} map, edges = {:?}",
+        config.mode.name(),
+        map.width,
+        map.height,
+        map.edges
+    );
+
+    let context = Arc::new(RwLock::new(GameContext::new(&config, map)));
+
+    let chat_filter: Box<dyn ChatFilter> = match &config.chat_blocklist {
+        Some(path) => Box::new(
+            Blocklist::load(path).map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?,
+        ),
+        None => Box::new(Blocklist::default()),
+    };
+
+    let details = ServerDetails {
+        name: config.name.clone(),
+        started: Instant::now(),
+        clock: Clock::start(),
+        counters: Arc::new(Counters::default()),
+    };
 
     let mut poll = Poll::new()?;
     let mut events = Events::with_capacity(1024);
-    let mut snake_id = 1;
+    let mut next_token = 1;
+    let mut accept_limiter = AcceptLimiter::default();
 
-    let clients: Arc<RwLock<HashMap<Token, TcpStream>>> = Arc::new(RwLock::new(HashMap::new()));
+    let clients: Clients = Arc::new(RwLock::new(HashMap::new()));
+    let bans = BanList::load(&config.ban_list)
+        .map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?;
+    let bans: Bans = Arc::new(RwLock::new(bans));
 
     let mut listener = TcpListener::bind(format!("0.0.0.0:{PORT}").parse().unwrap())?;
 
@@ -269,74 +1220,276 @@ fn main() -> io::Result<()> {
 
     println!("INFO: TCP Socket listening to port {PORT}");
 
-    setup_gameloop(&context, &clients);
+    // Another server may already answer on this host, play without being discoverable then
+    let discovery_addr = format!("0.0.0.0:{DISCOVERY_PORT}").parse().unwrap();
+    let discovery_socket = match UdpSocket::bind(discovery_addr) {
+        Ok(mut socket) => {
+            poll.registry()
+                .register(&mut socket, DISCOVERY, Interest::READABLE)?;
 
-    loop {
-        if let Err(err) = poll.poll(&mut events, None) {
-            eprintln!("Failed to poll: {err}");
-            continue;
+            println!("INFO: Answering LAN discovery probes on UDP port {DISCOVERY_PORT}");
+            Some(socket)
         }
+        Err(err) => {
+            eprintln!(
+                "WARN: LAN discovery disabled, could not bind UDP port {DISCOVERY_PORT}: {err}"
+            );
+            None
+        }
+    };
 
-        for token in events.iter().map(|event| event.token()) {
-            match token {
-                Token(0) => match listener.accept() {
-                    Ok((mut stream, client_addr)) => {
-                        let clients = Arc::clone(&clients);
-                        let clients_map = clients.read().unwrap();
+    record_fullstate(&mut context.write().unwrap());
 
-                        if clients_map.len() > MAX_PLAYERS {
-                            let _ = stream.write_all(&[1, 0, 0x7]);
-                            continue;
-                        }
+    setup_gameloop(&context, &clients);
+    setup_heartbeat(&clients, details.clock, config.idle_timeout);
+
+    let (mut shutdown, shutdown_handle) = ShutdownListener::register(poll.registry(), SHUTDOWN)?;
+
+    Console::new(
+        &context,
+        &clients,
+        &bans,
+        &details.counters,
+        config.edges,
+        shutdown_handle,
+    )
+    .spawn();
+    println!("INFO: Admin console ready, type help for the commands");
+
+    let notice = 'serve: loop {
+        match poll.poll(&mut events, None) {
+            Ok(()) => {}
+            // A signal arrived, it is handled once its byte is read from the shutdown pipe
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => {
+                eprintln!("Failed to poll: {err}");
+                continue;
+            }
+        }
 
-                        while clients_map.contains_key(&Token(snake_id)) {
-                            snake_id = (snake_id + 1) % 254;
+        for event in events.iter() {
+            match event.token() {
+                SHUTDOWN => {
+                    if let Some(notice) = shutdown.notice() {
+                        break 'serve notice;
+                    }
+                }
+                SERVER => accept_clients(
+                    &listener,
+                    &mut poll,
+                    &mut next_token,
+                    &clients,
+                    &bans,
+                    &mut accept_limiter,
+                    &details.counters,
+                ),
+                DISCOVERY => {
+                    if let Some(socket) = &discovery_socket {
+                        answer_discovery(socket, &details, &context, &clients);
+                    }
+                }
+                token => {
+                    // The socket takes more of what was queued for the client
+                    if event.is_writable() {
+                        if let Some(client) = clients.write().unwrap().get_mut(&token) {
+                            let _ = client.flush();
                         }
 
-                        // Release the lock
-                        drop(clients_map);
-
-                        let token = Token(snake_id);
-
-                        match poll
-                            .registry()
-                            .register(&mut stream, token, Interest::READABLE)
-                        {
-                            Ok(_) => {
-                                let context = Arc::clone(&context);
-                                let mut clients = clients.write().unwrap();
-
-                                {
-                                    let mut context = context.write().unwrap();
-
-                                    context.spawn_snake(snake_id as u8);
-                                    broadcast_snake(
-                                        &mut clients,
-                                        snake_id as u8,
-                                        context.snakes.get(&(snake_id as u8)).unwrap(),
-                                    );
-                                }
-
-                                if send_fullstate(snake_id as u8, &mut stream, &context).is_ok() {
-                                    clients.insert(token, stream);
-
-                                    println!(
-                                        "INFO: Client {client_addr} connected, with token {snake_id}!"
-                                    );
-                                }
-                            }
-                            Err(err) => eprintln!("Could not register stream {err}"),
+                        if !event.is_readable() && !event.is_read_closed() && !event.is_error() {
+                            continue;
                         }
                     }
-                    Err(err) => eprintln!("Accept call failed: {err}"),
-                },
-                token => {
+
                     let context = Arc::clone(&context);
                     let clients = Arc::clone(&clients);
 
-                    client_read(&mut poll, context, clients, token);
+                    client_read(
+                        &mut poll,
+                        &details,
+                        chat_filter.as_ref(),
+                        context,
+                        clients,
+                        &bans,
+                        token,
+                    );
                 }
             }
         }
+    };
+
+    shut_down(&mut poll, listener, &details, &clients, &notice);
+
+    if let Err(err) = save_stats(
+        &details.counters,
+        &config.stats_file,
+        details.started.elapsed(),
+    ) {
+        eprintln!("ERROR: {err}");
+    }
+
+    if let Err(err) = save_replay(&context.read().unwrap().replay, &config.replay_file) {
+        eprintln!("ERROR: {err}");
+    }
+
+    println!("INFO: Server stopped");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Read, net};
+
+    use super::*;
+    use crate::client::tests::connected_client;
+
+    struct Server {
+        details: ServerDetails,
+        context: Arc<RwLock<GameContext>>,
+        clients: Clients,
+        bans: Bans,
+    }
+
+    impl Server {
+        fn new() -> Self {
+            Self {
+                details: ServerDetails {
+                    name: "Test server".to_string(),
+                    started: Instant::now(),
+                    clock: Clock::start(),
+                    counters: Arc::new(Counters::default()),
+                },
+                context: Arc::new(RwLock::new(GameContext::new(
+                    &Config::default(),
+                    Map::default(),
+                ))),
+                clients: Arc::new(RwLock::new(HashMap::new())),
+                // A missing file is an empty list
+                bans: Arc::new(RwLock::new(BanList::load("/nonexistent/bans.txt").unwrap())),
+            }
+        }
+
+        /// Connects a client, returns the other end of its connection
+        fn connect(&self, token: Token) -> net::TcpStream {
+            let (client, peer) = connected_client();
+            self.clients.write().unwrap().insert(token, client);
+
+            peer
+        }
+
+        fn receive(&self, token: Token, packet: Vec<u8>) {
+            let chat_filter = Blocklist::default();
+
+            handle_packet(
+                &self.details,
+                &chat_filter,
+                &self.context,
+                &self.clients,
+                &self.bans,
+                token,
+                &packet[2..],
+            );
+        }
+    }
+
+    fn join_packet(spectate: bool) -> Vec<u8> {
+        let mut packet = PacketBuilder::new(PacketType::Join);
+        packet.write(0);
+        packet.write(spectate as u8);
+        packet.write_str("player");
+        packet.write_u64_le(0);
+        packet.write(CAPABILITIES);
+
+        packet.build()
+    }
+
+    #[test]
+    fn status_counts_players_and_spectators() {
+        let server = Server::new();
+
+        let _peers: Vec<_> = [(1, false), (2, false), (3, true)]
+            .into_iter()
+            .map(|(token, spectate)| {
+                let peer = server.connect(Token(token));
+                server.receive(Token(token), join_packet(spectate));
+                peer
+            })
+            .collect();
+
+        let mut peer = server.connect(Token(4));
+        server.receive(
+            Token(4),
+            PacketBuilder::new(PacketType::StatusRequest).build(),
+        );
+
+        // The connection is closed once the status is sent
+        let mut bytes = Vec::new();
+        peer.read_to_end(&mut bytes).unwrap();
+
+        let mut packet = ReadablePacket::from_bytes(&bytes[2..]);
+        let status = ServerStatus::from_packet(&mut packet).unwrap();
+
+        assert_eq!(status.players.len(), 2);
+        assert_eq!(status.spectators, 1);
+        assert_eq!(status.name, "Test server");
+    }
+
+    #[test]
+    fn silent_clients_time_out() {
+        let server = Server::new();
+        let mut idle = server.connect(Token(1));
+        let mut active = server.connect(Token(2));
+
+        let idle_timeout = Duration::from_secs(15);
+        let ping = heartbeat::ping_packet(server.details.clock.timestamp());
+
+        {
+            let mut clients = server.clients.write().unwrap();
+            clients.get_mut(&Token(1)).unwrap().last_seen -= idle_timeout * 2;
+
+            ping_clients(&mut clients, &ping, idle_timeout);
+        }
+
+        // The idle client is cut off without a ping, the active one is pinged
+        let mut bytes = Vec::new();
+        idle.read_to_end(&mut bytes).unwrap();
+        assert!(bytes.is_empty());
+
+        let mut bytes = vec![0; ping.len()];
+        active.read_exact(&mut bytes).unwrap();
+        assert_eq!(bytes, ping);
+    }
+
+    #[test]
+    fn pong_gives_the_round_trip_time() {
+        let server = Server::new();
+        let _peer = server.connect(Token(1));
+
+        // Answers a ping sent 40 ms ago
+        let sent = server.details.clock.timestamp().wrapping_sub(40);
+        server.receive(Token(1), heartbeat::pong_packet(sent));
+
+        let ping = server.clients.read().unwrap()[&Token(1)].ping.unwrap();
+        assert!(ping >= Duration::from_millis(40) && ping < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn clients_without_snapshots_are_refused() {
+        let server = Server::new();
+        let mut peer = server.connect(Token(1));
+
+        // As sent before the capabilities existed
+        let mut packet = PacketBuilder::new(PacketType::Join);
+        packet.write(0);
+        packet.write(0);
+        packet.write_str("player");
+        packet.write_u64_le(0);
+        server.receive(Token(1), packet.build());
+
+        let mut bytes = Vec::new();
+        peer.read_to_end(&mut bytes).unwrap();
+
+        let packet = ReadablePacket::from_bytes(&bytes[2..]);
+        assert!(packet.r#type == PacketType::ConnRejected);
+        assert!(server.context.read().unwrap().snakes.is_empty());
     }
 }
@@ -0,0 +1,80 @@
+use std::time::Instant;
+
+use common::{
+    packet::{CAP_COMPRESSION, CAP_PACKED_STEPS},
+    snapshot::Snapshot,
+};
+
+/// Protocol features the packets of a replay use
+pub const REPLAY_CAPABILITIES: u8 = CAP_PACKED_STEPS | CAP_COMPRESSION;
+/// Recording stops once the replay gets this big
+const MAX_REPLAY_LEN: usize = 64 << 20;
+
+/// Records the game as a spectator sees it, to be saved when the server shuts down.
+///
+/// Each entry is the time since the recording started in milliseconds (u32), the length
+/// of the data (u32), then the frames sent at that time, as a client gets them.
+pub struct Replay {
+    started: Instant,
+    data: Vec<u8>,
+    /// Last recorded snapshot, the next ones are recorded as deltas against it
+    last_snapshot: Option<Snapshot>,
+    full: bool,
+}
+
+impl Default for Replay {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            data: Vec::new(),
+            last_snapshot: None,
+            full: false,
+        }
+    }
+}
+
+impl Replay {
+    /// Records frames sent to the clients
+    pub fn record(&mut self, frames: &[u8]) {
+        if self.full {
+            return;
+        }
+
+        if self.data.len() + 8 + frames.len() > MAX_REPLAY_LEN {
+            eprintln!("WARN: The replay is full, recording stopped");
+            self.full = true;
+            return;
+        }
+
+        let time = self.started.elapsed().as_millis() as u32;
+
+        self.data.extend_from_slice(&time.to_le_bytes());
+        self.data
+            .extend_from_slice(&(frames.len() as u32).to_le_bytes());
+        self.data.extend_from_slice(frames);
+    }
+
+    /// Records the whole game state, as at the start of a round. It holds a keyframe,
+    /// so the next snapshot is recorded whole too.
+    pub fn record_state(&mut self, state: &[u8]) {
+        self.record(state);
+        self.last_snapshot = None;
+    }
+
+    /// Records what changed since the last recorded snapshot
+    pub fn record_snapshot(&mut self, snapshot: &Snapshot) {
+        let update = match &self.last_snapshot {
+            Some(last) if last.seq == snapshot.seq => return,
+            // Replays always pack the steps of the snakes
+            Some(last) => snapshot.delta(last, true),
+            None => snapshot.keyframe(),
+        };
+
+        self.record(&update.to_packet_for(REPLAY_CAPABILITIES));
+        self.last_snapshot = Some(snapshot.clone());
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
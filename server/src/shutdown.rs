@@ -0,0 +1,141 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, ErrorKind, Read, Write},
+    os::unix::net::UnixStream as StdUnixStream,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use common::shutdown::ShutdownNotice;
+use mio::{net::UnixStream, Interest, Registry, Token};
+
+use crate::{flood::Counters, replay::Replay};
+use signal_hook::{
+    consts::{SIGINT, SIGTERM},
+    low_level::pipe,
+};
+
+/// Reason given to the clients when none was given, as when the server is stopped by a signal
+pub const SHUTDOWN_REASON: &str = "The server is shutting down";
+
+/// Wakes the main loop up when the server has to stop, on SIGINT, SIGTERM or from the console
+pub struct ShutdownListener {
+    receiver: UnixStream,
+    requested: Arc<Mutex<Option<ShutdownNotice>>>,
+}
+
+/// Asks the main loop to stop the server, with a reason for the clients
+pub struct ShutdownHandle {
+    sender: StdUnixStream,
+    requested: Arc<Mutex<Option<ShutdownNotice>>>,
+}
+
+impl ShutdownListener {
+    pub fn register(registry: &Registry, token: Token) -> io::Result<(Self, ShutdownHandle)> {
+        let (sender, receiver) = StdUnixStream::pair()?;
+        sender.set_nonblocking(true)?;
+        receiver.set_nonblocking(true)?;
+
+        // The signal handlers only write a byte to the pipe, everything else happens in the main loop
+        for signal in [SIGINT, SIGTERM] {
+            pipe::register(signal, sender.try_clone()?)?;
+        }
+
+        let mut receiver = UnixStream::from_std(receiver);
+        registry.register(&mut receiver, token, Interest::READABLE)?;
+
+        let requested = Arc::new(Mutex::new(None));
+
+        let handle = ShutdownHandle {
+            sender,
+            requested: Arc::clone(&requested),
+        };
+
+        Ok((
+            Self {
+                receiver,
+                requested,
+            },
+            handle,
+        ))
+    }
+
+    /// The reason to shut down with once woken up, none if the wake up was spurious
+    pub fn notice(&mut self) -> Option<ShutdownNotice> {
+        let mut woken = false;
+        let mut buffer = [0u8; 64];
+
+        loop {
+            match self.receiver.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(_) => woken = true,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(_) => break,
+            }
+        }
+
+        if !woken {
+            return None;
+        }
+
+        // Nothing requested means a signal
+        Some(
+            self.requested
+                .lock()
+                .unwrap()
+                .take()
+                .unwrap_or(ShutdownNotice {
+                    reason: SHUTDOWN_REASON.to_string(),
+                    restart_in: None,
+                }),
+        )
+    }
+}
+
+impl ShutdownHandle {
+    pub fn request(&self, reason: &str, restart_in: Option<Duration>) {
+        *self.requested.lock().unwrap() = Some(ShutdownNotice {
+            reason: reason.to_string(),
+            restart_in,
+        });
+
+        let _ = (&self.sender).write_all(&[1]);
+    }
+}
+
+/// Appends the counters of this run to a file, one line per run
+pub fn save_stats(counters: &Counters, path: &str, uptime: Duration) -> Result<(), String> {
+    let count = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+    let stopped = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    let line = format!(
+        "stopped={} uptime={} connections_accepted={} connections_refused={} \
+         packets_received={} bytes_received={} flood_kicks={} temporary_bans={}\n",
+        stopped.as_secs(),
+        uptime.as_secs(),
+        count(&counters.connections_accepted),
+        count(&counters.connections_refused),
+        count(&counters.packets_received),
+        count(&counters.bytes_received),
+        count(&counters.flood_kicks),
+        count(&counters.temporary_bans)
+    );
+
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .map_err(|err| format!("Could not save the stats to {path}: {err}"))
+}
+
+/// Writes the replay of this run to a file, replacing the one of the previous run
+pub fn save_replay(replay: &Replay, path: &str) -> Result<(), String> {
+    fs::write(path, replay.data())
+        .map_err(|err| format!("Could not save the replay to {path}: {err}"))
+}